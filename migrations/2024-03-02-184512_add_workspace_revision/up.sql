@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE "workspace_revision" (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    description VARCHAR(248) NOT NULL,
+    content TEXT,
+    restored_from INTEGER,
+    created_at TIMESTAMP NOT NULL,
+    CONSTRAINT fk_workspace
+      FOREIGN KEY(workspace_id) 
+	  REFERENCES "workspace"(id),
+    CONSTRAINT fk_user
+      FOREIGN KEY(user_id) 
+	  REFERENCES "user"(id)
+);
+
+CREATE INDEX workspace_revision_workspace_id ON "workspace_revision" (workspace_id);
+
+-- seed every existing workspace with its current state so history starts somewhere
+INSERT INTO "workspace_revision"
+(workspace_id, user_id, name, description, content, created_at)
+SELECT id, user_id, name, description, content, COALESCE(updated_at, created_at)
+FROM "workspace";
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "workspace"
+DROP COLUMN rendered_html,
+DROP COLUMN rendered_hash;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE "workspace"
+ADD COLUMN rendered_html TEXT,
+ADD COLUMN rendered_hash VARCHAR(64);
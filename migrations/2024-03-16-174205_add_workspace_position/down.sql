@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX workspace_parent_id_position;
+
+ALTER TABLE "workspace"
+DROP COLUMN position;
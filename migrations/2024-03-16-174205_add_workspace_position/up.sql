@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE "workspace"
+ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+-- keep today's order, oldest first
+UPDATE "workspace"
+SET position = ordered.position
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY parent_id ORDER BY id) - 1 AS position
+    FROM "workspace"
+) AS ordered
+WHERE "workspace".id = ordered.id;
+
+CREATE INDEX workspace_parent_id_position ON "workspace" (parent_id, position);
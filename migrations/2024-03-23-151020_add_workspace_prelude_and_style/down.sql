@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "workspace" DROP COLUMN style;
+ALTER TABLE "workspace" DROP COLUMN prelude;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "workspace" ADD COLUMN prelude TEXT;
+ALTER TABLE "workspace" ADD COLUMN style TEXT;
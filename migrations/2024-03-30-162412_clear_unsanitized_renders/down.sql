@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- the renders come back the next time each workspace is shown
+SELECT 1;
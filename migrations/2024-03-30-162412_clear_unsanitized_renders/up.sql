@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- renders saved before output was sanitized would still match their hash
+UPDATE "workspace"
+SET rendered_html = NULL,
+    rendered_hash = NULL;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "share_link" (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    CONSTRAINT fk_workspace
+      FOREIGN KEY(workspace_id) 
+	  REFERENCES "workspace"(id)
+);
+
+CREATE INDEX share_link_workspace_id ON "share_link" (workspace_id);
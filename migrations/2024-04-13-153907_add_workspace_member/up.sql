@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE "workspace_member" (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role VARCHAR(16) NOT NULL CHECK (role IN ('viewer', 'editor', 'admin')),
+    invited_by INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP,
+    CONSTRAINT fk_workspace
+      FOREIGN KEY(workspace_id) 
+	  REFERENCES "workspace"(id),
+    CONSTRAINT fk_user
+      FOREIGN KEY(user_id) 
+	  REFERENCES "user"(id),
+    CONSTRAINT fk_invited_by
+      FOREIGN KEY(invited_by) 
+	  REFERENCES "user"(id),
+    UNIQUE (workspace_id, user_id)
+);
+
+CREATE INDEX workspace_member_user_id ON "workspace_member" (user_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX session_user_id;
+ALTER TABLE "session" DROP COLUMN user_agent;
+ALTER TABLE "session" DROP COLUMN last_seen_at;
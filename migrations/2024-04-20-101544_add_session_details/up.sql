@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE "session" ADD COLUMN last_seen_at TIMESTAMP;
+ALTER TABLE "session" ADD COLUMN user_agent VARCHAR(512);
+
+CREATE INDEX session_user_id ON "session" (user_id);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "session" ADD COLUMN is_remembered BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX session_token_hash;
+ALTER TABLE "session" DROP COLUMN token_hash;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- sessions were found by their sequential id, which anyone could guess, so
+-- every one of them goes and their cookies stop working
+DELETE FROM "session";
+ALTER TABLE "session" ADD COLUMN token_hash VARCHAR(64) NOT NULL;
+CREATE UNIQUE INDEX session_token_hash ON "session" (token_hash);
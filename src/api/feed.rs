@@ -2,7 +2,12 @@
 macro_rules! feed_api {
     () => {
         routes::feed::feed().and_then(handlers::feed::feed)
+            .map(|reply| warp::reply::with_header(reply, "Access-Control-Allow-Origin", "*"))
         .or(routes::feed::workspace().and_then(handlers::feed::workspace))
+        .or(routes::feed::workspace_stylesheet().and_then(handlers::user::stylesheet))
+        .or(routes::feed::shared().and_then(handlers::feed::workspace))
+        .or(routes::feed::shared_stylesheet().and_then(handlers::user::stylesheet))
+        .or(routes::feed::renamed().map(warp::redirect::temporary))
             .with(warp::trace::named("feed"))
     };
 }
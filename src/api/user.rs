@@ -41,6 +41,34 @@ macro_rules! user_api {
                 // prelude form
                 routes::user::update_style()
                 .and_then(handlers::user::edit_style))
+            .or(
+                // where they are logged in, and logging out elsewhere
+                routes::user::sessions()
+                .or(routes::user::revoke_session())
+                .unify()
+                .or(routes::user::revoke_other_sessions())
+                .unify()
+                .and_then(handlers::user::sessions))
+            .or(
+                // their account, and changes to it
+                routes::user::settings()
+                .or(routes::user::change_password())
+                .unify()
+                .or(routes::user::change_username())
+                .unify()
+                .and_then(handlers::user::settings))
+            .or(
+                // making sure before the account goes
+                routes::user::delete_account_page()
+                .and_then(handlers::user::delete_account_page))
+            .or(
+                // the account is gone, so they are logged out too
+                routes::user::delete_account()
+                .and_then(handlers::user::logout))
+            .or(
+                // the stylesheet itself, for their pages
+                routes::user::stylesheet()
+                .and_then(handlers::user::stylesheet))
             .or(
                 // redirect login page to profile if signed in
                 routes::user::login_form()
@@ -7,6 +7,23 @@ macro_rules! workspace_api {
             .or(routes::workspace::edit().and_then(handlers::workspace::workspace))
             .or(routes::workspace::publish().and_then(handlers::workspace::workspace))
             .or(routes::workspace::edit_page().and_then(handlers::workspace::edit_workspace))
+            .or(routes::workspace::preview().and_then(handlers::workspace::preview))
+            .or(routes::workspace::move_page().and_then(handlers::workspace::move_page))
+            .or(routes::workspace::move_workspace().and_then(handlers::workspace::workspace))
+            .or(routes::workspace::reorder().and_then(handlers::workspace::workspace))
+            .or(routes::workspace::delete().and_then(handlers::workspace::workspace))
+            .or(routes::workspace::trash().and_then(handlers::workspace::trash))
+            .or(routes::workspace::restore_from_trash().and_then(handlers::workspace::workspace))
+            .or(routes::workspace::share_page().and_then(handlers::workspace::share))
+            .or(routes::workspace::new_share_link().and_then(handlers::workspace::share))
+            .or(routes::workspace::revoke_share_link().and_then(handlers::workspace::share))
+            .or(routes::workspace::members_page().and_then(handlers::workspace::members))
+            .or(routes::workspace::invite_member().and_then(handlers::workspace::members))
+            .or(routes::workspace::remove_member().and_then(handlers::workspace::members))
+            .or(routes::workspace::shared().and_then(handlers::workspace::shared))
+            .or(routes::workspace::history().and_then(handlers::workspace::history))
+            .or(routes::workspace::revision_diff().and_then(handlers::workspace::revision_diff))
+            .or(routes::workspace::restore().and_then(handlers::workspace::workspace))
             .with(warp::trace::named("workspace"))
     };
 }
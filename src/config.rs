@@ -7,6 +7,30 @@ const MAX_INFLIGHT_REQUESTS: usize = 100;
 // how many connections can be open an running at one time
 // the rest wait until a permit opens up
 const MAX_CONNS: usize = 100;
+// how much work a single bebop program may do before it is stopped
+const BEBOP_MAX_STEPS: usize = 100_000;
+// stack given to each bebop thread, deep recursion is cut off well before it
+const BEBOP_STACK_KB: usize = 16 * 1024;
+// how long a single bebop program may run
+const BEBOP_TIMEOUT_MS: u64 = 2_000;
+// how many bebop programs may run at once, the rest wait their turn
+const BEBOP_MAX_WORKERS: usize = 8;
+// how much html a single bebop program may build up
+const BEBOP_MAX_OUTPUT_KB: usize = 1024;
+// how many rendered workspaces we keep in memory
+const RENDER_CACHE_SIZE: usize = 512;
+// how long deleted workspaces sit in the trash before they are gone for good
+const TRASH_RETENTION_DAYS: i64 = 30;
+// how often the trash is checked for workspaces past their retention
+const TRASH_PURGE_INTERVAL_SECS: u64 = 60 * 60;
+// how long a session lasts without being used
+const SESSION_IDLE_MINUTES: i64 = 4 * 60;
+// how long a session lasts without being used when asked to remember it
+const SESSION_REMEMBER_DAYS: i64 = 30;
+// how stale a session may get before a request pushes its expiry forward
+const SESSION_TOUCH_INTERVAL_SECS: i64 = 60;
+// how often expired sessions are cleared out
+const SESSION_SWEEP_INTERVAL_SECS: u64 = 60 * 60;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -18,6 +42,19 @@ pub struct Config {
     pub tls: bool,
     pub cert_path: Option<String>,
     pub key_path: Option<String>,
+    pub bebop_max_steps: usize,
+    pub bebop_stack_kb: usize,
+    pub bebop_timeout_ms: u64,
+    pub bebop_max_workers: usize,
+    pub bebop_max_output_kb: usize,
+    pub render_cache_size: usize,
+    pub render_cache_persist: bool,
+    pub trash_retention_days: i64,
+    pub trash_purge_interval_secs: u64,
+    pub session_idle_minutes: i64,
+    pub session_remember_days: i64,
+    pub session_touch_interval_secs: i64,
+    pub session_sweep_interval_secs: u64,
 }
 
 impl Config {
@@ -58,6 +95,102 @@ impl Config {
             key_path = None;
         }
 
+        // bebop sandbox
+        let bebop_max_steps = match env::var("BEBOP_MAX_STEPS") {
+            Ok(s) => s
+                .parse::<usize>()
+                .expect("BEBOP_MAX_STEPS must be an integer"),
+            Err(_) => BEBOP_MAX_STEPS,
+        };
+
+        let bebop_stack_kb = match env::var("BEBOP_STACK_KB") {
+            Ok(s) => s
+                .parse::<usize>()
+                .expect("BEBOP_STACK_KB must be an integer"),
+            Err(_) => BEBOP_STACK_KB,
+        };
+
+        let bebop_timeout_ms = match env::var("BEBOP_TIMEOUT_MS") {
+            Ok(t) => t
+                .parse::<u64>()
+                .expect("BEBOP_TIMEOUT_MS must be an integer"),
+            Err(_) => BEBOP_TIMEOUT_MS,
+        };
+
+        let bebop_max_workers = match env::var("BEBOP_MAX_WORKERS") {
+            Ok(w) => w
+                .parse::<usize>()
+                .expect("BEBOP_MAX_WORKERS must be an integer"),
+            Err(_) => BEBOP_MAX_WORKERS,
+        };
+
+        let bebop_max_output_kb = match env::var("BEBOP_MAX_OUTPUT_KB") {
+            Ok(o) => o
+                .parse::<usize>()
+                .expect("BEBOP_MAX_OUTPUT_KB must be an integer"),
+            Err(_) => BEBOP_MAX_OUTPUT_KB,
+        };
+
+        // render cache
+        let render_cache_size = match env::var("RENDER_CACHE_SIZE") {
+            Ok(s) => s
+                .parse::<usize>()
+                .expect("RENDER_CACHE_SIZE must be an integer"),
+            Err(_) => RENDER_CACHE_SIZE,
+        };
+
+        // also keep renders in the database so they survive restarts
+        let render_cache_persist = match env::var("RENDER_CACHE_PERSIST") {
+            Ok(p) => p
+                .parse()
+                .expect("RENDER_CACHE_PERSIST must be true or false"),
+            Err(_) => true,
+        };
+
+        // trash
+        let trash_retention_days = match env::var("TRASH_RETENTION_DAYS") {
+            Ok(d) => d
+                .parse::<i64>()
+                .expect("TRASH_RETENTION_DAYS must be an integer"),
+            Err(_) => TRASH_RETENTION_DAYS,
+        };
+
+        let trash_purge_interval_secs = match env::var("TRASH_PURGE_INTERVAL_SECS") {
+            Ok(i) => i
+                .parse::<u64>()
+                .expect("TRASH_PURGE_INTERVAL_SECS must be an integer"),
+            Err(_) => TRASH_PURGE_INTERVAL_SECS,
+        };
+
+        // sessions
+        let session_idle_minutes = match env::var("SESSION_IDLE_MINUTES") {
+            Ok(m) => m
+                .parse::<i64>()
+                .expect("SESSION_IDLE_MINUTES must be an integer"),
+            Err(_) => SESSION_IDLE_MINUTES,
+        };
+
+        let session_remember_days = match env::var("SESSION_REMEMBER_DAYS") {
+            Ok(d) => d
+                .parse::<i64>()
+                .expect("SESSION_REMEMBER_DAYS must be an integer"),
+            Err(_) => SESSION_REMEMBER_DAYS,
+        };
+
+        let session_touch_interval_secs = match env::var("SESSION_TOUCH_INTERVAL_SECS") {
+            Ok(i) => i
+                .parse::<i64>()
+                .expect("SESSION_TOUCH_INTERVAL_SECS must be an integer"),
+            Err(_) => SESSION_TOUCH_INTERVAL_SECS,
+        };
+
+        let session_sweep_interval_secs = match env::var("SESSION_SWEEP_INTERVAL_SECS") {
+            Ok(i) => i
+                .parse::<u64>()
+                .expect("SESSION_SWEEP_INTERVAL_SECS must be an integer"),
+            Err(_) => SESSION_SWEEP_INTERVAL_SECS,
+        };
+
         Config {
             app_addr,
             max_conn,
@@ -67,6 +200,19 @@ impl Config {
             tls,
             cert_path,
             key_path,
+            bebop_max_steps,
+            bebop_stack_kb,
+            bebop_timeout_ms,
+            bebop_max_workers,
+            bebop_max_output_kb,
+            render_cache_size,
+            render_cache_persist,
+            trash_retention_days,
+            trash_purge_interval_secs,
+            session_idle_minutes,
+            session_remember_days,
+            session_touch_interval_secs,
+            session_sweep_interval_secs,
         }
     }
 }
@@ -75,6 +221,32 @@ pub fn generate_config() -> Config {
     Config::new(false)
 }
 
+pub fn generate_test_config() -> Config {
+    Config {
+        app_addr: String::from("127.0.0.1:3030"),
+        max_conn: MAX_CONNS,
+        max_reqs: MAX_INFLIGHT_REQUESTS,
+        is_mocking: true,
+        db_path: db_test_url(),
+        tls: false,
+        cert_path: None,
+        key_path: None,
+        bebop_max_steps: BEBOP_MAX_STEPS,
+        bebop_stack_kb: BEBOP_STACK_KB,
+        bebop_timeout_ms: BEBOP_TIMEOUT_MS,
+        bebop_max_workers: BEBOP_MAX_WORKERS,
+        bebop_max_output_kb: BEBOP_MAX_OUTPUT_KB,
+        render_cache_size: RENDER_CACHE_SIZE,
+        render_cache_persist: true,
+        trash_retention_days: TRASH_RETENTION_DAYS,
+        trash_purge_interval_secs: TRASH_PURGE_INTERVAL_SECS,
+        session_idle_minutes: SESSION_IDLE_MINUTES,
+        session_remember_days: SESSION_REMEMBER_DAYS,
+        session_touch_interval_secs: SESSION_TOUCH_INTERVAL_SECS,
+        session_sweep_interval_secs: SESSION_SWEEP_INTERVAL_SECS,
+    }
+}
+
 pub fn db_test_url() -> String {
     dotenv().ok();
     env::var("DATABASE_URL_TEST").expect("DATABASE_URL must be set")
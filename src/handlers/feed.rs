@@ -1,4 +1,4 @@
-use crate::{models, views, Context};
+use crate::{handlers, models, views, Context};
 
 pub async fn feed(
     _context: Context,
@@ -14,11 +14,26 @@ pub async fn feed(
 }
 
 pub async fn workspace(
-    _context: Context,
+    context: Context,
     expanded_user: Option<models::user::ExpandedUser>,
     workspace: models::feed::FeedWorkspace,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let workspace_html = views::feed::workspace_page(expanded_user, workspace);
+    let refreshed_cookie = expanded_user
+        .as_ref()
+        .and_then(|expanded_user| expanded_user.refreshed_cookie.clone());
+    let content = workspace
+        .workspace
+        .render_content(
+            &context,
+            &workspace.user,
+            &workspace.tree,
+            models::workspace::Audience::Public,
+        )
+        .await;
+    let workspace_html = views::feed::workspace_page(expanded_user, workspace, content);
 
-    Ok(warp::reply::html(workspace_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(workspace_html),
+        refreshed_cookie,
+    ))
 }
@@ -3,19 +3,45 @@ pub mod user;
 pub mod workspace;
 
 use crate::{models, views};
+use warp::{
+    http::header::{HeaderValue, SET_COOKIE},
+    Reply,
+};
+
+/// The reply, with the session cookie sent again when the request slid a
+/// remembered session, so the browser holds on to it as long as it lasts.
+pub fn with_refreshed_cookie(reply: impl Reply, refreshed_cookie: Option<String>) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    if let Some(value) = refreshed_cookie.and_then(|cookie| HeaderValue::from_str(&cookie).ok()) {
+        response.headers_mut().insert(SET_COOKIE, value);
+    }
+    response
+}
 
 pub async fn index(
     expanded_user: Option<models::user::ExpandedUser>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user
+        .as_ref()
+        .and_then(|expanded_user| expanded_user.refreshed_cookie.clone());
     let landing_html = views::common::landing_page(expanded_user);
 
-    Ok(warp::reply::html(landing_html))
+    Ok(with_refreshed_cookie(
+        warp::reply::html(landing_html),
+        refreshed_cookie,
+    ))
 }
 
 pub async fn bebop(
     expanded_user: Option<models::user::ExpandedUser>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user
+        .as_ref()
+        .and_then(|expanded_user| expanded_user.refreshed_cookie.clone());
     let landing_html = views::common::bebop_page(expanded_user);
 
-    Ok(warp::reply::html(landing_html))
+    Ok(with_refreshed_cookie(
+        warp::reply::html(landing_html),
+        refreshed_cookie,
+    ))
 }
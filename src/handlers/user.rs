@@ -1,24 +1,53 @@
-use crate::{models, views, Context, NotFound, ResourceError};
+use crate::{handlers, models, utils::etag, views, Context, InvalidSignup, NotFound, ServerError};
 use std::convert::Infallible;
 use warp::{hyper::StatusCode, Rejection, Reply};
 
 pub async fn profile(
-    _context: Context,
+    context: Context,
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let profile_html = views::user::profile_page(expanded_user, workspace);
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let content = workspace
+        .workspace
+        .render_content(
+            &context,
+            &expanded_user.user,
+            &workspace.tree,
+            models::workspace::Audience::Owner,
+        )
+        .await;
+    let profile_html = views::user::profile_page(expanded_user, workspace, content);
 
-    Ok(warp::reply::html(profile_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(profile_html),
+        refreshed_cookie,
+    ))
 }
 
 pub async fn profile_with_cookie(
-    _context: Context,
+    context: Context,
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let cookie_value = format!("session={}; path=/", expanded_user.session.id);
-    let profile_html = views::user::profile_page(expanded_user, workspace);
+    // a remembered session outlives the browser, the others end with it
+    let cookie_value = models::session::cookie(
+        &expanded_user.token,
+        expanded_user
+            .session
+            .is_remembered
+            .then(|| models::session::lifetime(&context.config, true)),
+    );
+    let content = workspace
+        .workspace
+        .render_content(
+            &context,
+            &expanded_user.user,
+            &workspace.tree,
+            models::workspace::Audience::Owner,
+        )
+        .await;
+    let profile_html = views::user::profile_page(expanded_user, workspace, content);
 
     Ok(warp::reply::with_header(
         warp::reply::html(profile_html),
@@ -31,12 +60,15 @@ pub async fn logout() -> Result<impl warp::Reply, Infallible> {
     Ok(warp::reply::with_header(
         warp::reply::html(views::common::landing_page(None)),
         "Set-Cookie",
-        format!("session=; Path=/"),
+        models::session::CLEAR_COOKIE,
     ))
 }
 
 pub async fn signup_form() -> Result<impl warp::Reply, Infallible> {
-    Ok(warp::reply::html(views::auth::signup_form(None)))
+    Ok(warp::reply::html(views::auth::signup_form(
+        "",
+        &models::user::SignupErrors::default(),
+    )))
 }
 
 pub async fn login_form() -> Result<impl warp::Reply, Infallible> {
@@ -56,10 +88,10 @@ pub async fn login_error(err: Rejection) -> Result<impl Reply, Rejection> {
 
 pub async fn signup_error(err: Rejection) -> Result<impl Reply, Rejection> {
     tracing::error!("{:?}", err);
-    if let Some(e) = err.find::<ResourceError>() {
-        let html = views::auth::login_form(Some(e.message.clone()));
+    if let Some(e) = err.find::<InvalidSignup>() {
+        let html = views::auth::signup_form(&e.username, &e.errors);
         let html = warp::reply::html(html);
-        Ok(warp::reply::with_status(html, StatusCode::NOT_FOUND))
+        Ok(warp::reply::with_status(html, StatusCode::BAD_REQUEST))
     } else {
         Err(err)
     }
@@ -70,9 +102,79 @@ pub async fn edit_style(
     expanded_user: models::user::ExpandedUser,
     message: Option<String>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
     let style_html = views::user::style_edit_page(expanded_user, message);
 
-    Ok(warp::reply::html(style_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(style_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn stylesheet(
+    _context: Context,
+    css: String,
+    if_none_match: Option<String>,
+) -> Result<warp::http::Response<String>, warp::Rejection> {
+    let etag = etag(&css);
+    let response = warp::http::Response::builder()
+        .header("Content-Type", "text/css; charset=utf-8")
+        .header("Cache-Control", "public, max-age=300, must-revalidate")
+        .header("ETag", etag.clone());
+
+    let response = if if_none_match.as_deref() == Some(etag.as_str()) {
+        response.status(StatusCode::NOT_MODIFIED).body(String::new())
+    } else {
+        response.body(css)
+    };
+
+    response.map_err(|e| {
+        tracing::error!("{:?}", e);
+        warp::reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })
+}
+
+pub async fn sessions(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    sessions: Vec<models::session::Session>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let sessions_html = views::user::sessions_page(expanded_user, sessions);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(sessions_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn settings(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    message: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let settings_html = views::user::settings_page(expanded_user, message);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(settings_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn delete_account_page(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let delete_html = views::user::delete_account_page(expanded_user);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(delete_html),
+        refreshed_cookie,
+    ))
 }
 
 pub async fn edit_prelude(
@@ -80,7 +182,11 @@ pub async fn edit_prelude(
     expanded_user: models::user::ExpandedUser,
     message: Option<String>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
     let prelude_html = views::user::prelude_edit_page(expanded_user, message);
 
-    Ok(warp::reply::html(prelude_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(prelude_html),
+        refreshed_cookie,
+    ))
 }
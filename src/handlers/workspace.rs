@@ -1,21 +1,177 @@
-use crate::{models, views, Context};
+use crate::{handlers, models, views, Context};
 
 pub async fn workspace(
-    _context: Context,
+    context: Context,
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let workspace_html = views::workspace::workspace_page(expanded_user, workspace);
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let content = workspace
+        .workspace
+        .render_content(
+            &context,
+            &workspace.author,
+            &workspace.tree,
+            workspace.audience(expanded_user.user.id),
+        )
+        .await;
+    let workspace_html = views::workspace::workspace_page(expanded_user, workspace, content);
 
-    Ok(warp::reply::html(workspace_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(workspace_html),
+        refreshed_cookie,
+    ))
 }
 
 pub async fn edit_workspace(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let content = workspace
+        .workspace
+        .render_content(
+            &context,
+            &workspace.author,
+            &workspace.tree,
+            workspace.audience(expanded_user.user.id),
+        )
+        .await;
+    let workspace_html = views::workspace::edit_workspace_page(expanded_user, workspace, content);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(workspace_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn move_page(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    workspaces: Vec<models::workspace::Workspace>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let move_html = views::workspace::move_page(expanded_user, workspace, workspaces);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(move_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn trash(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    trashed: Vec<models::workspace::TrashedWorkspace>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let trash_html = views::workspace::trash_page(
+        expanded_user,
+        trashed,
+        context.config.trash_retention_days,
+    );
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(trash_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn preview(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let content = workspace
+        .workspace
+        .render_preview(
+            &context,
+            &workspace.author,
+            &workspace.tree,
+            workspace.audience(expanded_user.user.id),
+        )
+        .await;
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(views::workspace::preview(&content)),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn history(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    revisions: Vec<models::workspace_revision::RevisionWithAuthor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let history_html = views::workspace::history_page(expanded_user, workspace, revisions);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(history_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn share(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    links: Vec<models::share_link::ShareLink>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let share_html = views::workspace::share_page(expanded_user, workspace, links);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(share_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn members(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    members: Vec<models::workspace_member::MemberWithUser>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let members_html = views::workspace::members_page(expanded_user, workspace, members);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(members_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn shared(
+    _context: Context,
+    expanded_user: models::user::ExpandedUser,
+    shared: Vec<models::workspace_member::SharedWorkspace>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let shared_html = views::workspace::shared_page(expanded_user, shared);
+
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(shared_html),
+        refreshed_cookie,
+    ))
+}
+
+pub async fn revision_diff(
     _context: Context,
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    from: models::workspace_revision::WorkspaceRevision,
+    to: models::workspace_revision::WorkspaceRevision,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let workspace_html = views::workspace::edit_workspace_page(expanded_user, workspace);
+    let refreshed_cookie = expanded_user.refreshed_cookie.clone();
+    let diff_html = views::workspace::revision_diff_page(expanded_user, workspace, from, to);
 
-    Ok(warp::reply::html(workspace_html))
+    Ok(handlers::with_refreshed_cookie(
+        warp::reply::html(diff_html),
+        refreshed_cookie,
+    ))
 }
@@ -0,0 +1,62 @@
+use crate::{
+    models::{session, workspace::Workspace},
+    utils::now,
+    Context,
+};
+use std::time::Duration;
+
+/// Empties the trash of anything older than the retention window, every
+/// `trash_purge_interval_secs`, for as long as the server is up.
+pub fn spawn_trash_purge(context: Context) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(context.config.trash_purge_interval_secs));
+
+        loop {
+            interval.tick().await;
+
+            let context = context.clone();
+            let purged = tokio::task::spawn_blocking(move || {
+                let before = now() - chrono::Duration::days(context.config.trash_retention_days);
+                let mut conn = context.db_conn.get_conn();
+                Workspace::purge_trash(&mut conn, before)
+            })
+            .await;
+
+            match purged {
+                Ok(Ok(0)) => (),
+                Ok(Ok(count)) => tracing::info!("🗑 Purged {} workspaces from the trash", count),
+                Ok(Err(e)) => tracing::error!("Could not purge the trash: {:?}", e),
+                Err(e) => tracing::error!("Trash purge did not finish: {:?}", e),
+            }
+        }
+    })
+}
+
+/// Clears out sessions that ran out or were logged out, every
+/// `session_sweep_interval_secs`. They can't be used again, this only
+/// keeps the table from growing forever.
+pub fn spawn_session_sweep(context: Context) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(context.config.session_sweep_interval_secs));
+
+        loop {
+            interval.tick().await;
+
+            let context = context.clone();
+            let swept = tokio::task::spawn_blocking(move || {
+                let mut conn = context.db_conn.get_conn();
+                session::sweep(&mut conn)
+            })
+            .await;
+
+            match swept {
+                Ok(Ok(0)) => (),
+                Ok(Ok(count)) => tracing::info!("🧹 Swept {} expired sessions", count),
+                Ok(Err(e)) => tracing::error!("Could not sweep sessions: {:?}", e),
+                Err(e) => tracing::error!("Session sweep did not finish: {:?}", e),
+            }
+        }
+    })
+}
@@ -2,8 +2,12 @@ pub mod api;
 pub mod config;
 pub mod db_conn;
 pub mod handlers;
+pub mod jobs;
 pub mod models;
+pub mod program;
+pub mod render_cache;
 pub mod routes;
+pub mod sandbox;
 pub mod schema;
 pub mod utils;
 pub mod views;
@@ -36,6 +40,14 @@ pub struct ResourceError {
 }
 impl reject::Reject for ResourceError {}
 
+// a signup to send back to the form, with what was wrong with each field
+#[derive(Debug)]
+pub struct InvalidSignup {
+    username: String,
+    errors: models::user::SignupErrors,
+}
+impl reject::Reject for InvalidSignup {}
+
 #[derive(Debug)]
 pub struct ServerError {
     message: String,
@@ -52,18 +64,38 @@ impl reject::Reject for ExpandedUserRejection {}
 pub struct Context {
     pub config: Arc<config::Config>,
     pub db_conn: Arc<db_conn::DbConn>,
+    pub sandbox: Arc<sandbox::Sandbox>,
+    pub render_cache: Arc<render_cache::RenderCache>,
 }
 
 impl Context {
     pub fn new(config: Arc<config::Config>, db_conn: Arc<db_conn::DbConn>) -> Self {
+        let sandbox = Arc::new(sandbox::Sandbox::new(&config));
+        let render_cache = Arc::new(render_cache::RenderCache::new(config.render_cache_size));
         Context {
             config: config,
             db_conn,
+            sandbox,
+            render_cache,
         }
     }
 }
 
 pub async fn handle_rejections(err: Rejection) -> Result<impl Reply, Rejection> {
+    // a cookie that leads nowhere anymore is dropped, so the browser stops
+    // sending it and the visitor is simply logged out
+    let is_old_cookie = err.find::<OldCookie>().is_some();
+    let mut response = rejection_page(err).await?.into_response();
+    if is_old_cookie {
+        response.headers_mut().insert(
+            warp::http::header::SET_COOKIE,
+            warp::http::HeaderValue::from_static(models::session::CLEAR_COOKIE),
+        );
+    }
+    Ok(response)
+}
+
+async fn rejection_page(err: Rejection) -> Result<impl Reply, Rejection> {
     let expanded_user = err
         .find::<ExpandedUserRejection>()
         .map(|eu| eu.clone().expanded_user)
@@ -83,13 +115,15 @@ pub async fn handle_rejections(err: Rejection) -> Result<impl Reply, Rejection>
                 views::error::error_page(code, "You are not authorized to do this", expanded_user);
             Ok(warp::reply::with_status(warp::reply::html(html), code))
         }
-        // else if let Some(_) = err.find::<OldCookie>() {
-        //     Ok(Box::new(warp::reply::with_header(
-        //         warp::reply::html(views::body::index("Your session has expired")),
-        //         "Set-Cookie",
-        //         format!("session=; Path=/"),
-        //     )))
-        // }
+        else if err.find::<OldCookie>().is_some() {
+            let code = StatusCode::UNAUTHORIZED;
+            let html = views::error::error_page(
+                code,
+                "Your session has expired, please log in again",
+                expanded_user,
+            );
+            Ok(warp::reply::with_status(warp::reply::html(html), code))
+        }
         else if let Some(_) = err.find::<reject::MissingCookie>() {
             let code = StatusCode::FORBIDDEN;
             let html = views::error::error_page(code, "You are not logged in", expanded_user);
@@ -128,13 +162,15 @@ pub async fn handle_rejections(err: Rejection) -> Result<impl Reply, Rejection>
                 views::error::error_page(code, "You are not authorized to do this", expanded_user);
             Ok(warp::reply::with_status(warp::reply::html(html), code))
         }
-        // else if let Some(_) = err.find::<OldCookie>() {
-        //     Ok(Box::new(warp::reply::with_header(
-        //         warp::reply::html(views::body::index("Your session has expired")),
-        //         "Set-Cookie",
-        //         format!("session=; Path=/"),
-        //     )))
-        // }
+        else if err.find::<OldCookie>().is_some() {
+            let code = StatusCode::UNAUTHORIZED;
+            let html = views::error::error_page(
+                code,
+                "Your session has expired, please log in again",
+                expanded_user,
+            );
+            Ok(warp::reply::with_status(warp::reply::html(html), code))
+        }
         // else if let Some(_) = err.find::<reject::MissingCookie>() {
         //     let code = StatusCode::FORBIDDEN;
         //     let html = views::error::error_page(code, "You are not logged in", expanded_user);
@@ -353,4 +389,16 @@ const GLOBAL_PRELUDE: &str = r#"concat
         (\ [n-1 nthn-1] [tail (nthn-1)]))])
 
 (fun [append n] [eval (cons concat n)])
+
+(fun [id-of record] [nth 0 record])
+
+(fun [title-of record] [nth 1 record])
+
+(fun [description-of record] [nth 2 record])
+
+(fun [url-of record] [nth 3 record])
+
+(fun [is-published record] [eval (nth 4 record)])
+
+(fun [updated-at-of record] [nth 5 record])
 "#;
\ No newline at end of file
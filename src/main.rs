@@ -15,7 +15,7 @@ use digitheque::{
     assets_api,
     config::Config,
     db_conn::DbConn,
-    handle_rejections, handlers, routes, user_api, feed_api,
+    handle_rejections, handlers, jobs, routes, user_api, feed_api,
     utils::{load_certs, load_private_key},
     workspace_api, Context,
 };
@@ -42,7 +42,15 @@ async fn main() -> Result<(), ()> {
     let db_conn = Arc::new(DbConn::new(&config.db_path));
     let context = Context::new(config.clone(), db_conn.clone());
 
+    jobs::spawn_trash_purge(context.clone());
+    jobs::spawn_session_sweep(context.clone());
+
+    // static files and the rss feeds are the same for everyone, so any site
+    // may read them, the feeds getting that from `feed_api`. Everything else,
+    // public pages too, answers with what the visitor's cookie unlocks and
+    // stays closed to other origins
     let end = assets_api!()
+        .map(|reply| reply::with_header(reply, "Access-Control-Allow-Origin", "*"))
         .or(user_api!())
         .or(workspace_api!())
         .or(routes::index().and_then(handlers::index))
@@ -52,7 +60,6 @@ async fn main() -> Result<(), ()> {
             // surface logged in data to errors
             routes::user::logged_in_rejection().and_then(handlers::user::profile),
         )
-        .map(|reply| reply::with_header(reply, "Access-Control-Allow-Origin", "*"))
         .recover(handle_rejections)
         .with(warp::trace::request());
 
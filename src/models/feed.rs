@@ -1,5 +1,5 @@
 use crate::{
-    models,
+    models::{self, workspace_member::Role},
     schema::{user, workspace},
     DOMAIN, USER_AGENT,
 };
@@ -18,7 +18,7 @@ impl Feed {
         username: String,
     ) -> Result<Option<Self>, diesel::result::Error> {
         let (item, root) = diesel::alias!(workspace as item, workspace as root);
-        user::table
+        let feed = user::table
             .left_join(
                 root.on(root
                     .field(workspace::user_id)
@@ -30,17 +30,49 @@ impl Feed {
                 item.on(user::id
                     .eq(item.field(workspace::user_id))
                     .and(item.field(workspace::type_id).ne(1))
-                    .and(item.field(workspace::is_published).eq(true))),
+                    .and(item.field(workspace::is_published).eq(true))
+                    .and(item.field(workspace::deleted_at).is_null())),
             )
-            .filter(item.field(workspace::deleted_at).is_null())
             .filter(user::deleted_at.is_null())
             .filter(user::username.eq(username))
+            .select((
+                models::user::User::as_select(),
+                root.fields(workspace::all_columns).nullable(),
+                item.fields(workspace::all_columns).nullable(),
+            ))
             .load::<(
                 models::user::User,
                 Option<models::workspace::Workspace>,
                 Option<models::workspace::Workspace>,
             )>(conn)
-            .map(|res| Self::from_joined(res))
+            .map(Self::from_joined)?;
+
+        match feed {
+            Some(mut feed) => {
+                feed.sort_items(conn)?;
+                Ok(Some(feed))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // items follow the order of the author's tree, same as their pages, and
+    // leave out those under a draft, which the public can't open
+    fn sort_items(&mut self, conn: &mut PgConnection) -> Result<(), diesel::result::Error> {
+        let workspaces = models::workspace::Workspace::read_by_user(conn, self.user.id)?;
+        let order: Vec<i32> = models::workspace::Workspace::published_only(&workspaces)
+            .into_iter()
+            .map(|workspace| workspace.id)
+            .collect();
+
+        self.items.retain(|item| order.contains(&item.id));
+        self.items.sort_by_key(|item| {
+            order
+                .iter()
+                .position(|id| *id == item.id)
+                .unwrap_or(order.len())
+        });
+        Ok(())
     }
 
     fn from_joined(
@@ -100,6 +132,12 @@ impl Feed {
 pub struct FeedWorkspace {
     pub user: models::user::User,
     pub workspace: models::workspace::Workspace,
+    // the author's whole hierarchy, only what is public is ever shown
+    pub tree: Vec<models::workspace::Workspace>,
+    // how a visitor got to a draft without it being published
+    pub share_link: Option<models::share_link::ShareLink>,
+    // what the one looking may do with it, if they were let in at all
+    pub role: Option<Role>,
 }
 
 impl FeedWorkspace {
@@ -108,15 +146,118 @@ impl FeedWorkspace {
         username: String,
         workspace_id: i32,
     ) -> Result<Option<Self>, diesel::result::Error> {
-        workspace::table
+        let workspace = workspace::table
             .left_join(user::table.on(user::id.eq(workspace::user_id)))
             .filter(workspace::deleted_at.is_null())
             .filter(user::deleted_at.is_null())
-            // .filter(workspace::is_published.eq(true))
+            // whether it is published is down to its ancestors too, see `is_public`
             .filter(workspace::id.eq(workspace_id))
             .filter(user::username.eq(username))
+            .select((
+                models::workspace::Workspace::as_select(),
+                Option::<models::user::User>::as_select(),
+            ))
+            .load::<(models::workspace::Workspace, Option<models::user::User>)>(conn)
+            .map(Self::from_joined)?;
+
+        match workspace {
+            Some(mut workspace) => {
+                workspace.tree = models::workspace::Workspace::read_tree_by_user(conn, workspace.user.id)?;
+                Ok(Some(workspace))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The workspace a share link leads to, while the link still works.
+    pub fn get_shared(
+        conn: &mut PgConnection,
+        token: &str,
+    ) -> Result<Option<Self>, diesel::result::Error> {
+        let link = match models::share_link::ShareLink::read_active_by_token(conn, token) {
+            Ok(link) => link,
+            Err(diesel::result::Error::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let workspace = workspace::table
+            .left_join(user::table.on(user::id.eq(workspace::user_id)))
+            .filter(workspace::deleted_at.is_null())
+            .filter(user::deleted_at.is_null())
+            .filter(workspace::id.eq(link.workspace_id))
+            .select((
+                models::workspace::Workspace::as_select(),
+                Option::<models::user::User>::as_select(),
+            ))
             .load::<(models::workspace::Workspace, Option<models::user::User>)>(conn)
-            .map(|res| Self::from_joined(res))
+            .map(Self::from_joined)?;
+
+        match workspace {
+            Some(mut workspace) => {
+                workspace.tree = models::workspace::Workspace::read_tree_by_user(conn, workspace.user.id)?;
+                workspace.share_link = Some(link);
+                Ok(Some(workspace))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Published all the way up from the root, so anyone may see it.
+    pub fn is_public(&self) -> bool {
+        self.workspace.is_public(&self.tree)
+    }
+
+    /// Drafts are left for their owner, who sees them as everyone else
+    /// would once they're published.
+    pub fn is_visible_to(&self, expanded_user: Option<&models::user::ExpandedUser>) -> bool {
+        self.is_public()
+            || expanded_user.is_some_and(|expanded_user| expanded_user.user.id == self.user.id)
+    }
+
+    /// What `user_id` may do with the workspace, if anything.
+    pub fn role_of(&self, conn: &mut PgConnection, user_id: i32) -> QueryResult<Option<Role>> {
+        let memberships = if self.workspace.is_owned_by(user_id) {
+            vec![]
+        } else {
+            models::workspace_member::WorkspaceMember::read_by_user(conn, user_id)?
+        };
+        Ok(Role::of(user_id, &self.workspace, &self.tree, &memberships))
+    }
+
+    /// Only those who could fix it are told what went wrong running it.
+    pub fn shows_diagnostics(&self) -> bool {
+        self.role.is_some_and(|role| role >= Role::Editor)
+    }
+
+    pub fn tree_html(&self) -> String {
+        let tree = models::workspace::Workspace::published_only(&self.tree);
+        models::workspace::Workspace::tree_html(&tree, &self.workspace, |workspace| {
+            workspace.link_to_public(&self.user.username)
+        })
+    }
+
+    pub fn breadcrumbs(&self) -> String {
+        let tree = models::workspace::Workspace::published_only(&self.tree);
+        self.workspace.breadcrumbs(&tree, |workspace| {
+            workspace.link_to_public(&self.user.username)
+        })
+    }
+
+    // a shared draft's own stylesheet comes through its link, as the public
+    // route won't serve it
+    pub fn stylesheets(&self) -> Vec<String> {
+        let stylesheets = self.workspace.stylesheets(&self.user, &self.tree);
+        match &self.share_link {
+            Some(link) => {
+                let public = format!("/{}/workspace/{}/", self.user.username, self.workspace.id);
+                let shared = format!("{}/", link.href());
+                stylesheets
+                    .into_iter()
+                    .map(|href| href.replacen(&public, &shared, 1))
+                    .collect()
+            }
+            None => stylesheets,
+        }
     }
 
     fn from_joined(
@@ -132,6 +273,9 @@ impl FeedWorkspace {
             (workspace, Some(user)) => Some(Self {
                 workspace: workspace.to_owned(),
                 user: user.to_owned(),
+                tree: vec![],
+                share_link: None,
+                role: None,
             }),
             _ => panic!("Damn something went wrong!"),
         }
@@ -1,4 +1,8 @@
 pub mod feed;
 pub mod session;
+pub mod share_link;
 pub mod user;
+pub mod username_redirect;
 pub mod workspace;
+pub mod workspace_member;
+pub mod workspace_revision;
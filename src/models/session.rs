@@ -1,8 +1,65 @@
-use crate::{models, schema::session, utils::now};
-use chrono::naive::NaiveDateTime;
+use crate::{config::Config, models, schema::session, utils::now};
+use chrono::{naive::NaiveDateTime, Duration};
 use diesel::prelude::*;
+use html_to_string_macro::html;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
-const SESSION_DURATION_MINUTES: i64 = 60;
+const USER_AGENT_MAX_LENGTH: usize = 512;
+// the browser keeps the cookie to itself, only sends it over https and
+// leaves it off requests other sites start, besides following a link here
+const COOKIE_ATTRIBUTES: &str = "Path=/; HttpOnly; Secure; SameSite=Lax";
+pub const CLEAR_COOKIE: &str = "session=; Path=/; HttpOnly; Secure; SameSite=Lax; Max-Age=0";
+
+/// The cookie that carries a session's token. Without a `max_age` the
+/// browser drops it once it closes.
+pub fn cookie(token: &str, max_age: Option<Duration>) -> String {
+    match max_age {
+        Some(max_age) => format!(
+            "session={}; {}; Max-Age={}",
+            token,
+            COOKIE_ATTRIBUTES,
+            max_age.num_seconds()
+        ),
+        None => format!("session={}; {}", token, COOKIE_ATTRIBUTES),
+    }
+}
+
+/// Only the hash of a token is kept, so a leaked table can't be used to
+/// log in as anyone.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What every form posted during the session has to carry, so a form on
+/// another site can't post as the user. It comes from the session's token
+/// without giving it away.
+pub fn csrf_token(token: &str) -> String {
+    hash_token(&format!("csrf {}", token))
+}
+
+pub fn csrf_input(csrf_token: &str) -> String {
+    html! {
+        <input type="hidden" name="csrf_token" value={csrf_token} />
+    }
+}
+
+// tokens are 64 hex characters, anything else is a cookie from before them
+pub fn is_well_formed(token: &str) -> bool {
+    token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// How long a session lasts from its last use.
+pub fn lifetime(config: &Config, is_remembered: bool) -> Duration {
+    if is_remembered {
+        Duration::days(config.session_remember_days)
+    } else {
+        Duration::minutes(config.session_idle_minutes)
+    }
+}
 
 #[derive(Clone, Debug, Identifiable, Associations, Selectable, Queryable, AsChangeset)]
 #[diesel(belongs_to(models::user::User))]
@@ -14,6 +71,10 @@ pub struct Session {
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub deleted_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub user_agent: Option<String>,
+    pub is_remembered: bool,
+    pub token_hash: String,
 }
 
 impl Session {
@@ -25,10 +86,58 @@ impl Session {
             created_at: self.created_at.clone(),
             updated_at: Some(now()),
             deleted_at: self.deleted_at.clone(),
+            last_seen_at: self.last_seen_at,
+            user_agent: self.user_agent.clone(),
+            is_remembered: self.is_remembered,
+            token_hash: self.token_hash.clone(),
+        }
+    }
+
+    pub fn last_seen(&self) -> NaiveDateTime {
+        self.last_seen_at.unwrap_or(self.created_at)
+    }
+
+    pub fn is_seen_recently(&self, config: &Config) -> bool {
+        self.last_seen() > now() - Duration::seconds(config.session_touch_interval_secs)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.valid_until < now()
+    }
+
+    pub fn device(&self) -> String {
+        self.user_agent
+            .clone()
+            .filter(|user_agent| !user_agent.trim().is_empty())
+            .unwrap_or(String::from("Unknown device"))
+    }
+
+    pub fn revoke_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/sessions/{}/revoke", self.id)} method="POST">
+                {csrf_input(csrf_token)}
+                <button type="submit">"Revoke"</button>
+            </form>
+        }
+    }
+
+    pub fn revoke_others_form(csrf_token: &str) -> String {
+        html! {
+            <form action="/sessions/revoke-others" method="POST"
+                onsubmit="return confirm('Log out everywhere else?')">
+                {csrf_input(csrf_token)}
+                <button type="submit">"Revoke all other sessions"</button>
+            </form>
         }
     }
 }
 
+// the fields of a form that posts nothing else
+#[derive(Deserialize)]
+pub struct CsrfApi {
+    pub csrf_token: String,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = session)]
 pub struct NewSession {
@@ -37,16 +146,31 @@ pub struct NewSession {
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub deleted_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub user_agent: Option<String>,
+    pub is_remembered: bool,
+    pub token_hash: String,
 }
 
 impl NewSession {
-    pub fn new(user_id: i32) -> Self {
+    pub fn new(
+        config: &Config,
+        user_id: i32,
+        token: &str,
+        user_agent: Option<String>,
+        is_remembered: bool,
+    ) -> Self {
         NewSession {
             user_id: user_id,
-            valid_until: now() + chrono::Duration::minutes(SESSION_DURATION_MINUTES),
+            valid_until: now() + lifetime(config, is_remembered),
             created_at: now(),
             updated_at: None,
             deleted_at: None,
+            last_seen_at: Some(now()),
+            user_agent: user_agent
+                .map(|user_agent| user_agent.chars().take(USER_AGENT_MAX_LENGTH).collect()),
+            is_remembered,
+            token_hash: hash_token(token),
         }
     }
 
@@ -74,6 +198,13 @@ pub fn read_by_id(conn: &mut PgConnection, id: i32) -> Result<Session, diesel::r
         .first::<Session>(conn)
 }
 
+pub fn read_by_token(conn: &mut PgConnection, token: &str) -> Result<Session, diesel::result::Error> {
+    session::table
+        .filter(session::token_hash.eq(hash_token(token)))
+        .filter(session::deleted_at.is_null())
+        .first::<Session>(conn)
+}
+
 pub fn read_by_user_id(
     conn: &mut PgConnection,
     user_id: i32,
@@ -84,6 +215,59 @@ pub fn read_by_user_id(
         .first::<Session>(conn)
 }
 
+/// Every session of the user's still going, the most recently used first.
+pub fn read_active_by_user_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Vec<Session>, diesel::result::Error> {
+    session::table
+        .filter(session::user_id.eq(user_id))
+        .filter(session::deleted_at.is_null())
+        .filter(session::valid_until.gt(now()))
+        .order((session::last_seen_at.desc().nulls_last(), session::id.desc()))
+        .load::<Session>(conn)
+}
+
+pub fn read_active_by_user_id_and_id(
+    conn: &mut PgConnection,
+    user_id: i32,
+    id: i32,
+) -> Result<Session, diesel::result::Error> {
+    session::table
+        .filter(session::user_id.eq(user_id))
+        .filter(session::id.eq(id))
+        .filter(session::deleted_at.is_null())
+        .first::<Session>(conn)
+}
+
+/// Marks the session as used now and pushes its expiry out by another
+/// lifetime, so it only runs out once it goes unused. Skipped when that
+/// was already done a moment ago, to spare a write on every request.
+/// Tells whether the expiry moved.
+pub fn touch(conn: &mut PgConnection, config: &Config, session: &mut Session) -> QueryResult<bool> {
+    if session.is_seen_recently(config) {
+        return Ok(false);
+    }
+
+    session.last_seen_at = Some(now());
+    session.valid_until = now() + lifetime(config, session.is_remembered);
+    diesel::update(session::table)
+        .filter(session::id.eq(session.id))
+        .set((
+            session::last_seen_at.eq(session.last_seen_at),
+            session::valid_until.eq(session.valid_until),
+        ))
+        .execute(conn)
+        .map(|updated| updated > 0)
+}
+
+/// Removes for good every session that has run out or was logged out.
+pub fn sweep(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::delete(session::table)
+        .filter(session::valid_until.lt(now()).or(session::deleted_at.is_not_null()))
+        .execute(conn)
+}
+
 pub fn delete(conn: &mut PgConnection, session: &Session) -> QueryResult<usize> {
     diesel::update(session)
         .set((session::deleted_at.eq(Some(now())),))
@@ -93,7 +277,17 @@ pub fn delete(conn: &mut PgConnection, session: &Session) -> QueryResult<usize>
 pub fn delete_by_user_id(conn: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
     diesel::update(session::dsl::session)
         .filter(session::user_id.eq(user_id))
-        .filter(session::deleted_at.is_not_null())
+        .filter(session::deleted_at.is_null())
+        .set((session::deleted_at.eq(Some(now())),))
+        .execute(conn)
+}
+
+// logs the user out everywhere but `session`
+pub fn delete_others(conn: &mut PgConnection, session: &Session) -> QueryResult<usize> {
+    diesel::update(session::dsl::session)
+        .filter(session::user_id.eq(session.user_id))
+        .filter(session::id.ne(session.id))
+        .filter(session::deleted_at.is_null())
         .set((session::deleted_at.eq(Some(now())),))
         .execute(conn)
 }
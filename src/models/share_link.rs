@@ -0,0 +1,157 @@
+use crate::{
+    models::session::csrf_input,
+    schema::share_link,
+    utils::{now, random_token},
+};
+use chrono::{naive::NaiveDateTime, Duration};
+use diesel::prelude::*;
+use html_to_string_macro::html;
+use serde::Deserialize;
+
+/// A secret link that lets anyone holding it read a workspace, published
+/// or not, without signing in.
+#[derive(Clone, Debug, Identifiable, Selectable, Queryable)]
+#[diesel(table_name = share_link)]
+pub struct ShareLink {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl ShareLink {
+    pub fn new(
+        conn: &mut PgConnection,
+        new_link: &NewShareLink,
+    ) -> Result<Self, diesel::result::Error> {
+        diesel::insert_into(share_link::table)
+            .values(new_link)
+            .get_result(conn)
+    }
+
+    // revoked links are gone for good, expired ones stay listed until revoked
+    pub fn read_by_workspace(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        share_link::table
+            .filter(share_link::workspace_id.eq(workspace_id))
+            .filter(share_link::revoked_at.is_null())
+            .order(share_link::id.desc())
+            .load::<Self>(conn)
+    }
+
+    pub fn read_by_workspace_and_id(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+        id: i32,
+    ) -> Result<Self, diesel::result::Error> {
+        share_link::table
+            .filter(share_link::workspace_id.eq(workspace_id))
+            .filter(share_link::id.eq(id))
+            .filter(share_link::revoked_at.is_null())
+            .first::<Self>(conn)
+    }
+
+    /// The link behind `token`, as long as it still works.
+    pub fn read_active_by_token(
+        conn: &mut PgConnection,
+        token: &str,
+    ) -> Result<Self, diesel::result::Error> {
+        share_link::table
+            .filter(share_link::token.eq(token))
+            .filter(share_link::revoked_at.is_null())
+            .filter(
+                share_link::expires_at
+                    .is_null()
+                    .or(share_link::expires_at.gt(now())),
+            )
+            .first::<Self>(conn)
+    }
+
+    pub fn revoke(&self, conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        diesel::update(share_link::table)
+            .filter(share_link::id.eq(self.id))
+            .set(share_link::revoked_at.eq(Some(now())))
+            .execute(conn)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now())
+    }
+
+    pub fn href(&self) -> String {
+        format!("/s/{}", self.token)
+    }
+
+    pub fn expiry(&self) -> String {
+        match self.expires_at {
+            Some(_) if self.is_expired() => String::from("Expired"),
+            Some(expires_at) => format!("Until {}", expires_at.format("%Y-%m-%d %H:%M")),
+            None => String::from("Never expires"),
+        }
+    }
+
+    pub fn revoke_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/share/{}/revoke", self.workspace_id, self.id)} method="POST">
+                {csrf_input(csrf_token)}
+                <button type="submit">"Revoke"</button>
+            </form>
+        }
+    }
+
+    pub fn new_form(workspace_id: i32, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/share", workspace_id)} method="POST">
+                {csrf_input(csrf_token)}
+                <label>
+                    <span>"Expires"</span>
+                    <select name="expires_in_days">
+                        <option value="0">"Never"</option>
+                        <option value="1">"In a day"</option>
+                        <option value="7" selected>"In a week"</option>
+                        <option value="30">"In a month"</option>
+                    </select>
+                </label>
+                <button type="submit">"Create link"</button>
+            </form>
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewShareLinkApi {
+    // no expiry at 0
+    pub expires_in_days: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = share_link)]
+pub struct NewShareLink {
+    pub workspace_id: i32,
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl NewShareLink {
+    pub fn new(workspace_id: i32, new_link: NewShareLinkApi) -> Self {
+        let created_at = now();
+        NewShareLink {
+            workspace_id,
+            token: random_token(),
+            created_at,
+            expires_at: match new_link.expires_in_days {
+                days if days > 0 => Some(created_at + Duration::days(days.min(365))),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PgConnection) -> Result<ShareLink, diesel::result::Error> {
+        ShareLink::new(conn, self)
+    }
+}
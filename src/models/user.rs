@@ -1,16 +1,64 @@
 use crate::{
     models,
+    program::Source,
     schema::{session, user},
-    utils::{encrypt, now, sanitize_html, verify},
-    DEFAULT_PRELUDE_CONTENT,
+    utils::{encrypt, etag, now, sanitize_css, verify},
+    DEFAULT_PRELUDE_CONTENT, GLOBAL_PRELUDE,
 };
 use chrono::naive::NaiveDateTime;
 use diesel::prelude::*;
 use serde::Deserialize;
 use html_to_string_macro::html;
 
+// the username column is a VARCHAR(48)
+const USERNAME_MAX_LENGTH: usize = 48;
+const PASSWORD_MIN_LENGTH: usize = 8;
+// bcrypt ignores anything past this many bytes
+const PASSWORD_MAX_BYTES: usize = 72;
+// usernames start urls, so none may take the place of one of ours
+const RESERVED_USERNAMES: [&str; 25] = [
+    "about", "admin", "api", "bebop", "feed", "fonts", "img", "login", "logout", "members",
+    "prelude", "root", "rss", "s", "sessions", "settings", "share", "shared", "signup", "static",
+    "style", "styles", "stylesheet", "trash", "workspace",
+];
+
+/// Why `username` can't be used, if it can't.
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() {
+        Err(String::from("Pick a username."))
+    } else if username.chars().count() > USERNAME_MAX_LENGTH {
+        Err(format!("Keep it to {} characters or fewer.", USERNAME_MAX_LENGTH))
+    } else if !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Err(String::from("Use only letters and numbers."))
+    } else if RESERVED_USERNAMES.contains(&username.to_lowercase().as_str()) {
+        Err(String::from("This username is reserved."))
+    } else {
+        Ok(())
+    }
+}
+
+/// Why `password` is too weak for the user called `username`, if it is.
+pub fn validate_password(username: &str, password: &str) -> Result<(), String> {
+    let has_letter = password.chars().any(char::is_alphabetic);
+    let has_other = password.chars().any(|c| !c.is_alphabetic());
+
+    if password.chars().count() < PASSWORD_MIN_LENGTH {
+        Err(format!("Use at least {} characters.", PASSWORD_MIN_LENGTH))
+    } else if password.len() > PASSWORD_MAX_BYTES {
+        Err(format!("Keep it to {} characters or fewer.", PASSWORD_MAX_BYTES))
+    } else if password.eq_ignore_ascii_case(username) {
+        Err(String::from("Don't use your username as your password."))
+    } else if !has_letter || !has_other {
+        Err(String::from("Mix letters with numbers or symbols."))
+    } else {
+        Ok(())
+    }
+}
+
+// read through `as_select`, by column name, never by position
 #[derive(Clone, Debug, Identifiable, Queryable, Selectable)]
 #[diesel(table_name = user)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
     pub id: i32,
     pub username: String,
@@ -18,14 +66,15 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub deleted_at: Option<NaiveDateTime>,
-    pub style: Option<String>,
     pub prelude: Option<String>,
+    pub style: Option<String>,
 }
 
 impl User {
     pub fn new(conn: &mut PgConnection, new_user: &NewUser) -> Result<Self, diesel::result::Error> {
         diesel::insert_into(user::table)
             .values(new_user)
+            .returning(User::as_returning())
             .get_result(conn)
     }
     
@@ -33,6 +82,7 @@ impl User {
         user::table
             .filter(user::id.eq(id))
             .filter(user::deleted_at.is_null())
+            .select(User::as_select())
             .first::<Self>(conn)
     }
     
@@ -40,6 +90,7 @@ impl User {
         user::table
             .filter(user::username.eq(username))
             .filter(user::deleted_at.is_null())
+            .select(User::as_select())
             .first::<Self>(conn)
     }
     
@@ -50,6 +101,7 @@ impl User {
         let user: User = user::table
             .filter(user::username.eq(credentials.username))
             .filter(user::deleted_at.is_null())
+            .select(User::as_select())
             .first::<User>(conn)?;
     
         if verify(&credentials.password, &user.password) {
@@ -59,11 +111,51 @@ impl User {
         }
     }
 
+    /// Closes the account. Its workspaces go to the trash and every
+    /// session ends, so nothing of it can be reached anymore.
     pub fn delete(&self, conn: &mut PgConnection) -> QueryResult<usize> {
-        diesel::update(self)
-            .set((user::deleted_at.eq(Some(now())),))
+        conn.transaction(|conn| {
+            models::workspace::Workspace::delete_by_user(conn, self.id)?;
+            models::session::delete_by_user_id(conn, self.id)?;
+            diesel::update(self)
+                .set((user::deleted_at.eq(Some(now())),))
+                .execute(conn)
+        })
+    }
+
+    pub fn update_password(&mut self, conn: &mut PgConnection, password: &str) -> QueryResult<usize> {
+        self.password = encrypt(password);
+        diesel::update(user::table)
+            .filter(user::id.eq(self.id))
+            .set((
+                user::updated_at.eq(Some(now())),
+                user::password.eq(self.password.clone()),
+            ))
             .execute(conn)
     }
+
+    /// Takes on a new name. The old one keeps pointing here, so links to
+    /// the feed and workspaces under it carry on working.
+    pub fn rename(&mut self, conn: &mut PgConnection, username: String) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            models::username_redirect::UsernameRedirect::release(conn, &username)?;
+            let renamed = diesel::update(user::table)
+                .filter(user::id.eq(self.id))
+                .set((
+                    user::updated_at.eq(Some(now())),
+                    user::username.eq(username.clone()),
+                ))
+                .execute(conn)?;
+            models::username_redirect::NewUsernameRedirect::new(self.username.clone(), self.id)
+                .insert(conn)?;
+            self.username = username;
+            Ok(renamed)
+        })
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        verify(password, &self.password)
+    }
     
     pub fn update(&self, conn: &mut PgConnection) -> QueryResult<usize> {
         diesel::update(user::table)
@@ -76,6 +168,37 @@ impl User {
             .execute(conn)
     }
 
+    /// The global prelude followed by this user's own, ready to be run
+    /// ahead of any of their content.
+    pub fn preludes(&self) -> Vec<Source> {
+        vec![
+            Source::new("global prelude", GLOBAL_PRELUDE),
+            Source::new("prelude", &self.prelude.clone().unwrap_or_default()),
+        ]
+    }
+
+    /// The stylesheet as it gets served, whatever was saved before it was
+    /// sanitized on the way in.
+    pub fn stylesheet(&self) -> String {
+        sanitize_css(&self.style.clone().unwrap_or_default())
+    }
+
+    pub fn stylesheet_etag(&self) -> String {
+        etag(&self.stylesheet())
+    }
+
+    // versioned so a changed stylesheet is picked up straight away
+    pub fn stylesheet_href(&self) -> Option<String> {
+        if self.style.clone().unwrap_or_default().trim().is_empty() {
+            return None;
+        }
+        Some(format!(
+            "/{}/style.css?v={}",
+            self.username,
+            self.stylesheet_etag().trim_matches('"')
+        ))
+    }
+
     pub fn link_to_prelude() -> String {
         html! {
             <a href="/prelude">"Edit prelude"</a>
@@ -102,19 +225,47 @@ pub struct NewUserApi {
     pub confirm_password: String,
 }
 
+impl NewUserApi {
+    /// Everything wrong with the signup, by field. Empty when it's fine.
+    pub fn validate(&self) -> SignupErrors {
+        SignupErrors {
+            username: validate_username(&self.username).err(),
+            password: validate_password(&self.username, &self.password).err(),
+            confirm_password: (self.password != self.confirm_password)
+                .then(|| String::from("The passwords do not match.")),
+        }
+    }
+}
+
 impl From<NewUserApi> for UserCredentialsEncrypted {
     fn from(creds: NewUserApi) -> Self {
         UserCredentialsEncrypted {
-            username: sanitize_html(&creds.username),
+            username: creds.username,
             password: encrypt(&creds.password),
         }
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct SignupErrors {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub confirm_password: Option<String>,
+}
+
+impl SignupErrors {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none() && self.password.is_none() && self.confirm_password.is_none()
+    }
+}
+
 #[derive(Deserialize)]
 pub struct UserCredentialsApi {
     pub username: String,
     pub password: String,
+    // the login form's "remember me", for a session that outlasts a few idle hours
+    #[serde(default)]
+    pub remember: bool,
 }
 
 impl From<UserCredentialsApi> for UserCredentialsEncrypted {
@@ -135,6 +286,16 @@ pub struct UserCredentialsEncrypted {
 pub struct ExpandedUser {
     pub user: User,
     pub session: models::session::Session,
+    // the token from the cookie, only its hash is stored
+    pub token: String,
+    // sent again with the reply when this request slid a remembered session
+    pub refreshed_cookie: Option<String>,
+}
+
+impl ExpandedUser {
+    pub fn csrf_token(&self) -> String {
+        models::session::csrf_token(&self.token)
+    }
 }
 
 #[derive(Insertable)]
@@ -167,26 +328,48 @@ impl NewUser {
     }
 }
 
-pub fn read_user_by_session(
+pub fn read_user_by_session_token(
     conn: &mut PgConnection,
-    session_id: i32,
+    token: &str,
 ) -> Result<ExpandedUser, diesel::result::Error> {
     let r = user::table
         .inner_join(session::table.on(user::id.eq(session::user_id)))
         // .filter(session::valid_until.gt(now()))
         .filter(session::deleted_at.is_null())
-        .filter(session::id.eq(session_id))
+        .filter(session::token_hash.eq(models::session::hash_token(token)))
         .filter(user::deleted_at.is_null())
         .select((User::as_select(), models::session::Session::as_select()))
         .first(conn);
 
-    r.map(|(user, session)| ExpandedUser { user, session })
+    r.map(|(user, session)| ExpandedUser {
+        user,
+        session,
+        token: token.to_string(),
+        refreshed_cookie: None,
+    })
 }
 
 pub fn cleanup_table(conn: &mut PgConnection) {
     diesel::delete(user::table).execute(conn).unwrap();
 }
 
+#[derive(Deserialize)]
+pub struct ChangePasswordApi {
+    pub current_password: String,
+    pub new_password: String,
+    pub confirm_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeUsernameApi {
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountApi {
+    pub password: String,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateStyleApi {
     pub style: String,
@@ -0,0 +1,70 @@
+use crate::{
+    models::user::User,
+    schema::{user, username_redirect},
+    utils::now,
+};
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+
+/// A name someone went by before renaming themselves, kept so links to
+/// their feed and workspaces under it still lead to them.
+#[derive(Clone, Debug, Identifiable, Selectable, Queryable)]
+#[diesel(table_name = username_redirect)]
+pub struct UsernameRedirect {
+    pub id: i32,
+    pub username: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl UsernameRedirect {
+    pub fn read_by_username(
+        conn: &mut PgConnection,
+        username: &str,
+    ) -> Result<Self, diesel::result::Error> {
+        username_redirect::table
+            .filter(username_redirect::username.eq(username))
+            .first::<Self>(conn)
+    }
+
+    /// Who `username` belonged to, as long as they are still around.
+    pub fn read_user(conn: &mut PgConnection, username: &str) -> Result<User, diesel::result::Error> {
+        username_redirect::table
+            .inner_join(user::table)
+            .filter(username_redirect::username.eq(username))
+            .filter(user::deleted_at.is_null())
+            .select(User::as_select())
+            .first::<User>(conn)
+    }
+
+    // going back to an old name takes it out of the redirects
+    pub fn release(conn: &mut PgConnection, username: &str) -> QueryResult<usize> {
+        diesel::delete(username_redirect::table)
+            .filter(username_redirect::username.eq(username))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = username_redirect)]
+pub struct NewUsernameRedirect {
+    pub username: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl NewUsernameRedirect {
+    pub fn new(username: String, user_id: i32) -> Self {
+        NewUsernameRedirect {
+            username,
+            user_id,
+            created_at: now(),
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PgConnection) -> Result<UsernameRedirect, diesel::result::Error> {
+        diesel::insert_into(username_redirect::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
@@ -1,15 +1,24 @@
 use crate::{
-    models::user::User,
-    schema::workspace,
-    utils::{now, sanitize_html},
-    DEFAULT_WORKSPACE_CONTENT, DOMAIN,
+    models::{
+        session::csrf_input,
+        user::User,
+        workspace_member::{Role, WorkspaceMember},
+        workspace_revision::NewWorkspaceRevision,
+    },
+    program::{string_literal, Diagnostic, ProgramText, RenderResult, Resolver, Source},
+    schema::{share_link, workspace, workspace_member, workspace_revision},
+    utils::{escape_html, escape_sanitized, etag, now, sanitize_css, sanitize_html, sanitize_output},
+    Context, DEFAULT_WORKSPACE_CONTENT, DOMAIN,
 };
 use chrono::naive::NaiveDateTime;
 use diesel::prelude::*;
 use html_to_string_macro::html;
 // use rss::{Channel, ChannelBuilder};
 use serde::Deserialize;
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
 
 #[derive(PartialEq)]
 pub enum WorkspaceType {
@@ -40,7 +49,7 @@ impl Display for WorkspaceType {
     }
 }
 
-#[derive(Clone, Debug, Identifiable, Selectable, Queryable, AsChangeset)]
+#[derive(Clone, Debug, Identifiable, Selectable, Queryable, QueryableByName, AsChangeset)]
 #[diesel(belongs_to(models::workspace_element::WorkspaceElement))]
 #[diesel(table_name = workspace)]
 pub struct Workspace {
@@ -55,6 +64,11 @@ pub struct Workspace {
     pub content: Option<String>,
     pub parent_id: i32,
     pub is_published: bool,
+    pub rendered_html: Option<String>,
+    pub rendered_hash: Option<String>,
+    pub position: i32,
+    pub prelude: Option<String>,
+    pub style: Option<String>,
 }
 
 impl Workspace {
@@ -67,13 +81,160 @@ impl Workspace {
             .get_result(conn)
     }
 
+    pub fn read_by_id(conn: &mut PgConnection, id: i32) -> Result<Workspace, diesel::result::Error> {
+        workspace::table
+            .filter(workspace::id.eq(id))
+            .filter(workspace::deleted_at.is_null())
+            .first::<Workspace>(conn)
+    }
+
+    pub fn read_by_user(
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Vec<Workspace>, diesel::result::Error> {
+        workspace::table
+            .filter(workspace::user_id.eq(user_id))
+            .filter(workspace::deleted_at.is_null())
+            .order(workspace::id)
+            .load::<Workspace>(conn)
+    }
+
+    // the same, held until the transaction they're read in is over
+    pub fn lock_by_user(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<Workspace>> {
+        workspace::table
+            .filter(workspace::user_id.eq(user_id))
+            .filter(workspace::deleted_at.is_null())
+            .order(workspace::id)
+            .for_update()
+            .load::<Workspace>(conn)
+    }
+
+    pub fn is_owned_by(&self, user_id: i32) -> bool {
+        self.user_id == user_id
+    }
+
+    /// Whether `ancestor_id` is somewhere up this workspace's parent chain,
+    /// walking through `workspaces`. A chain that loops back on itself is
+    /// treated as a match so it can never be made worse.
+    pub fn descends_from(&self, workspaces: &[Workspace], ancestor_id: i32) -> bool {
+        let mut seen = vec![self.id];
+        let mut parent_id = self.parent_id;
+
+        while let Some(parent) = workspaces.iter().find(|workspace| workspace.id == parent_id) {
+            if parent.id == ancestor_id || seen.contains(&parent.id) {
+                return true;
+            }
+            seen.push(parent.id);
+            parent_id = parent.parent_id;
+        }
+
+        false
+    }
+
+    /// Workspaces depth first from their roots, siblings in the order their
+    /// author gave them, each with its depth.
+    pub fn tree_order(workspaces: &[Workspace]) -> Vec<(usize, &Workspace)> {
+        Self::walk(workspaces, |workspace| workspace.is_root())
+    }
+
+    // the same, starting from one workspace instead of the roots
+    fn tree_order_from(workspaces: &[Workspace], id: i32) -> Vec<(usize, &Workspace)> {
+        Self::walk(workspaces, |workspace| workspace.id == id)
+    }
+
+    fn walk(
+        workspaces: &[Workspace],
+        is_start: impl Fn(&Workspace) -> bool,
+    ) -> Vec<(usize, &Workspace)> {
+        let mut ordered = vec![];
+        let mut stack: Vec<(usize, &Workspace)> = workspaces
+            .iter()
+            .filter(|workspace| is_start(workspace))
+            .map(|workspace| (0, workspace))
+            .collect();
+
+        while let Some((depth, workspace)) = stack.pop() {
+            ordered.push((depth, workspace));
+
+            let mut children: Vec<&Workspace> = workspaces
+                .iter()
+                .filter(|child| child.parent_id == workspace.id && !child.is_root())
+                .collect();
+            children.sort_by_key(|child| (child.position, child.id));
+            stack.extend(children.into_iter().rev().map(|child| (depth + 1, child)));
+        }
+
+        ordered
+    }
+
+    /// Everywhere this workspace could be moved to, in tree order with
+    /// their depth. Itself and anything under it are left out.
+    pub fn move_targets<'a>(&self, workspaces: &'a [Workspace]) -> Vec<(usize, &'a Workspace)> {
+        Self::tree_order(workspaces)
+            .into_iter()
+            .filter(|(_, workspace)| {
+                workspace.id != self.id && !workspace.descends_from(workspaces, self.id)
+            })
+            .collect()
+    }
+
+    // the position after the last of a parent's children
+    pub fn next_position(conn: &mut PgConnection, parent_id: i32) -> QueryResult<i32> {
+        workspace::table
+            .filter(workspace::parent_id.eq(parent_id))
+            .filter(workspace::deleted_at.is_null())
+            .select(diesel::dsl::max(workspace::position))
+            .first::<Option<i32>>(conn)
+            .map(|max| max.map(|max| max + 1).unwrap_or(0))
+    }
+
+    /// Swaps this workspace with its neighbouring sibling. Siblings are
+    /// renumbered from zero, which also straightens out any ties.
+    pub fn reorder(&self, conn: &mut PgConnection, direction: Direction) -> QueryResult<()> {
+        conn.transaction(|conn| {
+            let mut siblings = workspace::table
+                .filter(workspace::parent_id.eq(self.parent_id))
+                .filter(workspace::deleted_at.is_null())
+                .order((workspace::position, workspace::id))
+                .select(workspace::id)
+                .load::<i32>(conn)?;
+
+            if let Some(index) = siblings.iter().position(|id| *id == self.id) {
+                match direction {
+                    Direction::Up if index > 0 => siblings.swap(index, index - 1),
+                    Direction::Down if index + 1 < siblings.len() => siblings.swap(index, index + 1),
+                    _ => (),
+                }
+            }
+
+            for (position, id) in siblings.into_iter().enumerate() {
+                diesel::update(workspace::table)
+                    .filter(workspace::id.eq(id))
+                    .set(workspace::position.eq(position as i32))
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn reorder_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/reorder", self.id)} method="POST" class="reorder-workspace">
+                {csrf_input(csrf_token)}
+                <button type="submit" name="direction" value="up" title="Move up">"↑"</button>
+                <button type="submit" name="direction" value="down" title="Move down">"↓"</button>
+            </form>
+        }
+    }
+
     pub fn details(&self) -> String {
         html! {
             <dl id="workspace-details">
                 <dt>"Name"</dt>
-                <dd>{self.name.clone()}</dd>
+                <dd>{escape_sanitized(&self.name)}</dd>
                 <dt>"Description"</dt>
-                <dd>{self.description.clone()}</dd>
+                <dd>{escape_sanitized(&self.description)}</dd>
                 <dt>"Status"</dt>
                 <dd>{if self.is_published == true {
                     "published"
@@ -84,7 +245,8 @@ impl Workspace {
         }
     }
 
-    pub fn actions(&self, is_editing: bool) -> String {
+    // only what `role` is allowed to do
+    pub fn actions(&self, role: Role, csrf_token: &str, is_editing: bool) -> String {
         html! {
             <ul id="workspace-actions">
                 <li>
@@ -94,37 +256,72 @@ impl Workspace {
                         self.link_to_parent()
                     }}
                 </li>
-                <li>
-                    {if is_editing == true {
-                        html! { <a href={format!("/workspace/{}", self.id)}>"✕ Cancel edit"</a> }
+                {
+                    if role < Role::Editor {
+                        String::new()
+                    } else if is_editing {
+                        html! { <li><a href={format!("/workspace/{}", self.id)}>"✕ Cancel edit"</a></li> }
                     } else {
-                        self.link_to_edit()
-                    }}
-                </li>
+                        html! { <li>{self.link_to_edit()}</li> }
+                    }
+                }
+                <li>{self.link_to_history()}</li>
+                {
+                    if role < Role::Admin {
+                        String::new()
+                    } else {
+                        html! {
+                            <li>{self.link_to_share()}</li>
+                            <li>{self.link_to_members()}</li>
+                        }
+                    }
+                }
+                {
+                    if self.is_root() || role < Role::Admin {
+                        String::new()
+                    } else {
+                        html! {
+                            <li>{self.link_to_move()}</li>
+                            <li>{self.delete_form(csrf_token)}</li>
+                        }
+                    }
+                }
                 {
-                    if self.is_root() {
+                    if self.is_root() && role == Role::Owner {
                         html! {
                             <li>{User::link_to_prelude()}</li>
+                            <li>{Self::link_to_trash()}</li>
+                            <li>{Self::link_to_shared()}</li>
+                        }
+                    } else if !self.is_root() && role >= Role::Editor {
+                        html! {
+                            <li>{self.publish_form(csrf_token)}</li>
                         }
+                    } else {
+                        String::new()
+                    }
+                }
+                {
+                    if role < Role::Editor {
+                        String::new()
                     } else {
                         html! {
-                            <li>{self.publish_form()}</li>
+                            <li>
+                                <details>
+                                    <summary>"Add subworkspace"</summary>
+                                    {self.new_workspace_form(csrf_token)}
+                                </details>
+                            </li>
                         }
                     }
                 }
-                <li>
-                    <details>
-                        <summary>"Add subworkspace"</summary>
-                        {self.new_workspace_form()}
-                    </details>
-                </li>
             </ul>
         }
     }
 
     pub fn link_to_self(&self) -> String {
         html! {
-            <a href={format!("/workspace/{}", self.id)}>{self.name.clone()}</a>
+            <a href={format!("/workspace/{}", self.id)}>{escape_sanitized(&self.name)}</a>
         }
     }
 
@@ -133,9 +330,10 @@ impl Workspace {
     }
 
     // how do we get the API type in here...
-    pub fn new_workspace_form(&self) -> String {
+    pub fn new_workspace_form(&self, csrf_token: &str) -> String {
         html! {
             <form action={format!("/workspace/{}/new", self.id)} method="POST">
+                {csrf_input(csrf_token)}
                 <label>
                     <span>"Name"</span>
                     <input type="text" name="name" required max=64 />
@@ -154,9 +352,10 @@ impl Workspace {
         WorkspaceType::from_i32(self.type_id) == WorkspaceType::Root
     }
 
-    pub fn publish_form(&self) -> String {
+    pub fn publish_form(&self, csrf_token: &str) -> String {
         html! {
             <form action={format!("/workspace/{}/publish", self.id)} method="POST" id="publish-workspace">
+                {csrf_input(csrf_token)}
                 <input type="hidden" name="is_published" value={!self.is_published} />
                 <button type="submit" class="submit-publish">
                     {
@@ -168,18 +367,38 @@ impl Workspace {
         }
     }
 
-    pub fn edit_self_form(&self) -> String {
+    /// `diagnostics` sits alongside the textarea, so problems with the last
+    /// saved content show up right where they get fixed. Typing in the
+    /// textarea refreshes them along with the preview.
+    pub fn edit_self_form(&self, csrf_token: &str, diagnostics: &str) -> String {
         html! {
             <form action={format!("/workspace/{}", self.id)} method="POST">
+                {csrf_input(csrf_token)}
                 <label>
                     <span>"Name"</span>
-                    <input type="text" name="name" value={self.name.clone()} required max=64 />
+                    <input type="text" name="name" value={escape_sanitized(&self.name)} required max=64 />
                 </label>
                 <label>
                     <span>"Description"</span>
-                    <input type="text" name="description" value={self.description.clone()} required max=248 />
+                    <input type="text" name="description" value={escape_sanitized(&self.description)} required max=248 />
                 </label>
-                <textarea name="content">{self.content.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n"))}</textarea>
+                <div id="workspace-diagnostics">{diagnostics}</div>
+                <textarea
+                    name="content"
+                    data-preview={format!("/workspace/{}/preview", self.id)}
+                >{escape_html(&self.content.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n")))}</textarea>
+                <details {if self.prelude.is_some() || self.style.is_some() { "open" } else { "" }}>
+                    <summary>"Prelude and stylesheet"</summary>
+                    <p>"These apply to this workspace and everything under it, after your own prelude and stylesheet."</p>
+                    <label>
+                        <span>"Prelude"</span>
+                        <textarea name="prelude" class="code">{escape_html(&self.prelude.clone().unwrap_or_default())}</textarea>
+                    </label>
+                    <label>
+                        <span>"Stylesheet"</span>
+                        <textarea name="style" class="code">{escape_html(&self.style.clone().unwrap_or_default())}</textarea>
+                    </label>
+                </details>
                 <button type="submit">"Submit"</button>
             </form>
         }
@@ -192,57 +411,662 @@ impl Workspace {
         }
     }
 
-    pub fn execute_content(&self, prelude: String) -> String {
-        let input = self.content.clone().unwrap_or(String::from(""));
+    pub fn link_to_move(&self) -> String {
+        html! {
+            <a href={format!("/workspace/{}/move", self.id)}>"⇄ Move"</a>
+        }
+    }
+
+    pub fn move_form(&self, csrf_token: &str, workspaces: &[Workspace]) -> String {
+        let options = self
+            .move_targets(workspaces)
+            .into_iter()
+            .map(|(depth, workspace)| {
+                html! {
+                    <option value={workspace.id} {if workspace.id == self.parent_id { "selected" } else { "" }}>
+                        {format!("{}{}", "— ".repeat(depth), escape_sanitized(&workspace.name))}
+                    </option>
+                }
+            })
+            .collect::<String>();
+
+        html! {
+            <form action={format!("/workspace/{}/move", self.id)} method="POST">
+                {csrf_input(csrf_token)}
+                <label>
+                    <span>"New parent"</span>
+                    <select name="parent_id" required>
+                        {options}
+                    </select>
+                </label>
+                <button type="submit">"Move workspace"</button>
+            </form>
+        }
+    }
+
+    /// Every workspace hanging off the user's root, found in one recursive
+    /// query. Anything in the trash cuts its subtree off with it.
+    pub fn read_tree_by_user(
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Vec<Workspace>, diesel::result::Error> {
+        diesel::sql_query(
+            r#"WITH RECURSIVE tree AS (
+                SELECT * FROM "workspace"
+                WHERE user_id = $1 AND parent_id = -1 AND deleted_at IS NULL
+                UNION
+                SELECT child.* FROM "workspace" child
+                INNER JOIN tree ON child.parent_id = tree.id
+                WHERE child.deleted_at IS NULL
+            )
+            SELECT * FROM tree"#,
+        )
+        .bind::<diesel::sql_types::Integer, _>(user_id)
+        .load::<Workspace>(conn)
+    }
+
+    /// What of a tree a visitor may see, the root and anything published
+    /// whose ancestors are all published too.
+    pub fn published_only(tree: &[Workspace]) -> Vec<Workspace> {
+        let mut visible: Vec<i32> = vec![];
+        Self::tree_order(tree)
+            .into_iter()
+            .filter(|(_, workspace)| {
+                let is_visible = workspace.is_root()
+                    || (workspace.is_published && visible.contains(&workspace.parent_id));
+                if is_visible {
+                    visible.push(workspace.id);
+                }
+                is_visible
+            })
+            .map(|(_, workspace)| workspace.clone())
+            .collect()
+    }
+
+    pub fn is_public(&self, tree: &[Workspace]) -> bool {
+        Self::published_only(tree)
+            .iter()
+            .any(|workspace| workspace.id == self.id)
+    }
+
+    /// From the root down to here, not counting the workspace itself.
+    pub fn ancestors<'a>(&self, workspaces: &'a [Workspace]) -> Vec<&'a Workspace> {
+        let mut ancestors = vec![];
+        let mut parent_id = self.parent_id;
+
+        while let Some(parent) = workspaces.iter().find(|workspace| workspace.id == parent_id) {
+            if parent.id == self.id || ancestors.iter().any(|a: &&Workspace| a.id == parent.id) {
+                break;
+            }
+            ancestors.push(parent);
+            if parent.is_root() {
+                break;
+            }
+            parent_id = parent.parent_id;
+        }
+
+        ancestors.reverse();
+        ancestors
+    }
 
-        // parse markdown doc
-        let (_, md) = bebop_lang::markdown::parser::parse_markdown(&input).unwrap_or(("", vec![]));
-        let mut env = bebop_lang::lisp::env::init_env();
+    pub fn breadcrumbs(&self, workspaces: &[Workspace], link: impl Fn(&Workspace) -> String) -> String {
+        html! {
+            <nav class="breadcrumbs">
+                <ol>
+                    {self.ancestors(workspaces)
+                        .into_iter()
+                        .map(|workspace| html! { <li>{link(workspace)}</li> })
+                        .collect::<String>()}
+                    <li>{escape_sanitized(&self.name)}</li>
+                </ol>
+            </nav>
+        }
+    }
 
-        // convert each line into lisp
-        let lisp: String = md
+    /// The whole tree as nested lists, from its roots or from wherever it
+    /// was cut off above. Branches are collapsible and start open only
+    /// along the way to `current`.
+    pub fn tree_html(
+        workspaces: &[Workspace],
+        current: &Workspace,
+        link: impl Fn(&Workspace) -> String,
+    ) -> String {
+        let open: Vec<i32> = current
+            .ancestors(workspaces)
             .into_iter()
-            .map(bebop_lang::markdown::lisp::markdown_to_lisp)
+            .map(|workspace| workspace.id)
+            .chain(std::iter::once(current.id))
             .collect();
 
-        // build up program
-        let input = &format!(
-            r#"{}
-{}
-{}
-"#,
-            prelude,
-            self.get_lisp_values(),
-            lisp
-        );
+        html! {
+            <ul class="tree">
+                {workspaces
+                    .iter()
+                    .filter(|workspace| {
+                        workspace.is_root()
+                            || !workspaces.iter().any(|parent| parent.id == workspace.parent_id)
+                    })
+                    .map(|root| Self::branch_html(workspaces, root, current, &open, &link))
+                    .collect::<String>()}
+            </ul>
+        }
+    }
+
+    fn branch_html(
+        workspaces: &[Workspace],
+        workspace: &Workspace,
+        current: &Workspace,
+        open: &[i32],
+        link: &impl Fn(&Workspace) -> String,
+    ) -> String {
+        let mut children: Vec<&Workspace> = workspaces
+            .iter()
+            .filter(|child| child.parent_id == workspace.id && !child.is_root())
+            .collect();
+        children.sort_by_key(|child| (child.position, child.id));
+
+        let label = if workspace.id == current.id {
+            html! { <strong>{escape_sanitized(&workspace.name)}</strong> }
+        } else {
+            link(workspace)
+        };
+
+        if children.is_empty() {
+            return html! { <li>{label}</li> };
+        }
+
+        html! {
+            <li>
+                <details {if open.contains(&workspace.id) { "open" } else { "" }}>
+                    <summary>{label}</summary>
+                    <ul>
+                        {children
+                            .into_iter()
+                            .map(|child| Self::branch_html(workspaces, child, current, open, link))
+                            .collect::<String>()}
+                    </ul>
+                </details>
+            </li>
+        }
+    }
+
+    pub fn link_to_public(&self, username: &str) -> String {
+        html! {
+            <a href={format!("/{}/workspace/{}", username, self.id)}>{escape_sanitized(&self.name)}</a>
+        }
+    }
+
+    // where the owner sees what everyone else does, or would once published
+    pub fn link_to_public_preview(&self, username: &str, tree: &[Workspace]) -> String {
+        let href = format!("/{}/workspace/{}", username, self.id);
+        if self.is_public(tree) {
+            html! { <a href={href}>"↗ Public page"</a> }
+        } else {
+            html! { <a href={href}>"👁 Preview as public"</a> }
+        }
+    }
+
+    pub fn link_to_trash() -> String {
+        html! {
+            <a href="/trash">"♲ Trash"</a>
+        }
+    }
+
+    pub fn link_to_shared() -> String {
+        html! {
+            <a href="/shared">"⇄ Shared with you"</a>
+        }
+    }
+
+    pub fn link_to_members(&self) -> String {
+        html! {
+            <a href={format!("/workspace/{}/members", self.id)}>"👥 Members"</a>
+        }
+    }
+
+    pub fn link_to_share(&self) -> String {
+        html! {
+            <a href={format!("/workspace/{}/share", self.id)}>"🔗 Share"</a>
+        }
+    }
+
+    pub fn link_to_history(&self) -> String {
+        html! {
+            <a href={format!("/workspace/{}/history", self.id)}>"↺ History"</a>
+        }
+    }
+
+    /// Everything that runs ahead of this workspace, the user's preludes
+    /// and then those of its ancestors from the root down, ending with its
+    /// own.
+    pub fn preludes(&self, user: &User, tree: &[Workspace]) -> Vec<Source> {
+        let mut preludes = user.preludes();
+        preludes.extend(self.ancestors(tree).into_iter().filter_map(|ancestor| {
+            ancestor
+                .prelude
+                .as_ref()
+                .map(|prelude| Source::new(&format!("prelude of {}", ancestor.name), prelude))
+        }));
+        if let Some(prelude) = &self.prelude {
+            preludes.push(Source::new("workspace prelude", prelude));
+        }
+        preludes
+    }
+
+    pub fn stylesheet(&self) -> String {
+        sanitize_css(&self.style.clone().unwrap_or_default())
+    }
+
+    pub fn stylesheet_href(&self, username: &str) -> Option<String> {
+        if self.style.clone().unwrap_or_default().trim().is_empty() {
+            return None;
+        }
+        Some(format!(
+            "/{}/workspace/{}/style.css?v={}",
+            username,
+            self.id,
+            etag(&self.stylesheet()).trim_matches('"')
+        ))
+    }
+
+    /// The user's stylesheet, then the nearest ones down the tree, so the
+    /// workspace's own has the last word.
+    pub fn stylesheets(&self, user: &User, tree: &[Workspace]) -> Vec<String> {
+        user.stylesheet_href()
+            .into_iter()
+            .chain(
+                self.ancestors(tree)
+                    .into_iter()
+                    .chain(std::iter::once(self))
+                    .filter_map(|workspace| workspace.stylesheet_href(&user.username)),
+            )
+            .collect()
+    }
+
+    /// The content to run, after the preludes that apply to it and with its
+    /// place in `tree` bound to names.
+    pub fn program_text(&self, author: &User, tree: &[Workspace], audience: &Audience) -> ProgramText {
+        ProgramText {
+            preludes: self.preludes(author, tree),
+            values: self.get_lisp_values(author, tree, audience),
+            content: self.content.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Executes the content inside the sandbox, unless the same content and
+    /// preludes have been rendered before. Only renders without diagnostics
+    /// are cached, a program that runs out of budget is reported as one.
+    /// Neither is a render that includes other workspaces, as those can
+    /// change without this one changing.
+    pub async fn render_content(
+        &self,
+        context: &Context,
+        author: &User,
+        tree: &[Workspace],
+        audience: Audience,
+    ) -> RenderResult {
+        let program = self.program_text(author, tree, &audience);
+        let key = program.key();
+        if let Some(html) = context.render_cache.get(&key) {
+            return RenderResult {
+                html,
+                ..Default::default()
+            };
+        }
+
+        if let (Some(html), Some(hash)) = (&self.rendered_html, &self.rendered_hash) {
+            if *hash == key {
+                context.render_cache.insert(key, html.clone());
+                return RenderResult {
+                    html: html.clone(),
+                    ..Default::default()
+                };
+            }
+        }
+
+        let result = self.run(context, program, audience).await;
+        if result.diagnostics.is_empty() && result.includes.is_empty() {
+            context.render_cache.insert(key.clone(), result.html.clone());
+            if context.config.render_cache_persist {
+                let mut conn = context.db_conn.get_conn();
+                if let Err(e) = self.save_rendered(&mut conn, &key, &result.html) {
+                    tracing::error!("Could not save render of workspace {}: {:?}", self.id, e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Executes the content inside the sandbox without touching the cache,
+    /// for content that may never be saved. Either way, the html that comes
+    /// out is sanitized, the content going in is kept as written.
+    pub async fn render_preview(
+        &self,
+        context: &Context,
+        author: &User,
+        tree: &[Workspace],
+        audience: Audience,
+    ) -> RenderResult {
+        self.run(context, self.program_text(author, tree, &audience), audience).await
+    }
+
+    async fn run(&self, context: &Context, program: ProgramText, audience: Audience) -> RenderResult {
+        let resolve = Self::include_resolver(context, self.user_id, audience);
+        let id = self.id;
+        match context
+            .sandbox
+            .run(move || {
+                let mut result = program.compile().with_includes(id, resolve).run();
+                result.html = sanitize_output(&result.html);
+                result
+            })
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::warn!("Workspace {} did not finish: {}", self.id, e);
+                RenderResult::failed(Diagnostic::new("program", e.to_string()))
+            }
+        }
+    }
+
+    /// Looks up workspaces for `(include id)`. Anyone's published
+    /// workspaces can be included, the author's own drafts too when the
+    /// author is the one looking, and a member's when they were let into
+    /// them. Ones that can't be seen are reported the same as ones that
+    /// don't exist.
+    fn include_resolver(context: &Context, user_id: i32, audience: Audience) -> Resolver {
+        let db_conn = context.db_conn.clone();
+        Arc::new(move |id| {
+            let missing = || format!("There is no workspace {} to include", id);
+            let mut conn = db_conn.get_conn();
+
+            let workspace = Self::read_by_id(&mut conn, id).map_err(|_| missing())?;
+            let author = User::read_by_id(&mut conn, workspace.user_id).map_err(|_| missing())?;
+            let tree = Self::read_tree_by_user(&mut conn, workspace.user_id).map_err(|e| {
+                tracing::error!("{:?}", e);
+                missing()
+            })?;
+
+            // someone else's workspace is seen the way the public sees it
+            let audience = match &audience {
+                Audience::Owner if workspace.user_id == user_id => Audience::Owner,
+                Audience::Owner => Audience::Public,
+                audience => audience.clone(),
+            };
+            if !workspace.is_public(&tree) && !audience.is_let_into(&workspace, &tree) {
+                return Err(missing());
+            }
 
-        tracing::info!("{}", input);
+            Ok(workspace.program_text(&author, &tree, &audience).compile())
+        })
+    }
 
-        // execute
-        let v = bebop_lang::lisp::lisp(&mut env, input);
-        v
+    // leaves updated_at alone, rendering is not an edit
+    pub fn save_rendered(
+        &self,
+        conn: &mut PgConnection,
+        key: &str,
+        html: &str,
+    ) -> QueryResult<usize> {
+        diesel::update(workspace::table)
+            .filter(workspace::id.eq(self.id))
+            .set((
+                workspace::rendered_html.eq(Some(html)),
+                workspace::rendered_hash.eq(Some(key)),
+            ))
+            .execute(conn)
+    }
+
+    /// Drops every saved render of a user's workspaces, for when something
+    /// they all share, like the prelude, has changed.
+    pub fn clear_rendered_by_user(conn: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::update(workspace::table)
+            .filter(workspace::user_id.eq(user_id))
+            .set((
+                workspace::rendered_html.eq(None::<String>),
+                workspace::rendered_hash.eq(None::<String>),
+            ))
+            .execute(conn)
     }
 
-    pub fn get_lisp_values(&self) -> String {
+    /// Binds this workspace's details, and those of the workspaces around
+    /// it in `tree`, for its program. `children`, `parent`, `siblings` and
+    /// `author` are lists of records, read with `id-of`, `title-of` and the
+    /// like from the global prelude. Members see what they were let into
+    /// and the public only sees what is published.
+    pub fn get_lisp_values(&self, author: &User, tree: &[Workspace], audience: &Audience) -> String {
+        let published = Self::published_only(tree);
+        let visible: Vec<Workspace> = tree
+            .iter()
+            .filter(|workspace| {
+                audience.is_let_into(workspace, tree)
+                    || published.iter().any(|public| public.id == workspace.id)
+            })
+            .cloned()
+            .collect();
+        let url = |workspace: &Workspace| {
+            if audience.is_let_into(workspace, tree) {
+                format!("/workspace/{}", workspace.id)
+            } else {
+                format!("/{}/workspace/{}", author.username, workspace.id)
+            }
+        };
+        let records = |workspaces: Vec<&Workspace>| {
+            workspaces
+                .into_iter()
+                .map(|workspace| workspace.lisp_record(&url(workspace)))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+
+        let mut around: Vec<&Workspace> = visible
+            .iter()
+            .filter(|workspace| workspace.id != self.id)
+            .collect();
+        around.sort_by_key(|workspace| (workspace.position, workspace.id));
+        let children = around
+            .iter()
+            .copied()
+            .filter(|workspace| workspace.parent_id == self.id && !workspace.is_root())
+            .collect();
+        let parent = around
+            .iter()
+            .copied()
+            .filter(|workspace| workspace.id == self.parent_id && !self.is_root())
+            .collect();
+        let siblings = around
+            .iter()
+            .copied()
+            .filter(|workspace| {
+                workspace.parent_id == self.parent_id && !workspace.is_root() && !self.is_root()
+            })
+            .collect();
+        let root = visible.iter().find(|workspace| workspace.is_root());
+
         format!(
-            r#"(def [title] "{}")
-(def [description] "{}")
-(def [updated-at] "{}")
-(def [id] "{}")
+            r#"(def [title] {})
+(def [description] {})
+(def [updated-at] {})
+(def [id] {})
+(def [children] [{}])
+(def [parent] [{}])
+(def [siblings] [{}])
+(def [author] [[{} {} "" {} 1 {}]])
 "#,
-            self.name,
-            self.description,
-            self.updated_at.unwrap_or_default(),
-            self.id,
+            string_literal(&self.name),
+            string_literal(&self.description),
+            string_literal(&self.updated_at.unwrap_or_default().to_string()),
+            string_literal(&self.id.to_string()),
+            records(children),
+            records(parent),
+            records(siblings),
+            string_literal(&author.id.to_string()),
+            string_literal(&author.username),
+            string_literal(&root.map(url).unwrap_or_default()),
+            string_literal(&author.updated_at.unwrap_or(author.created_at).to_string()),
+        )
+    }
+
+    // id, title, description, url, published and updated-at, in the order
+    // the global prelude reads them
+    fn lisp_record(&self, url: &str) -> String {
+        format!(
+            "[{} {} {} {} {} {}]",
+            string_literal(&self.id.to_string()),
+            string_literal(&self.name),
+            string_literal(&self.description),
+            string_literal(url),
+            if self.is_published { 1 } else { 0 },
+            string_literal(&self.updated_at.unwrap_or(self.created_at).to_string()),
         )
     }
 
+    /// Moves this workspace and everything under it to the trash. They all
+    /// share one `deleted_at`, which is how a restore knows what to bring
+    /// back with it.
     pub fn delete(&self, conn: &mut PgConnection) -> QueryResult<usize> {
-        diesel::update(self)
+        conn.transaction(|conn| {
+            let workspaces = Self::read_by_user(conn, self.user_id)?;
+            let ids: Vec<i32> = std::iter::once(self.id)
+                .chain(
+                    workspaces
+                        .iter()
+                        .filter(|workspace| workspace.descends_from(&workspaces, self.id))
+                        .map(|workspace| workspace.id),
+                )
+                .collect();
+
+            diesel::update(workspace::table)
+                .filter(workspace::id.eq_any(ids))
+                .set((workspace::deleted_at.eq(Some(now())),))
+                .execute(conn)
+        })
+    }
+
+    // everything the user has, for when they leave
+    pub fn delete_by_user(conn: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::update(workspace::table)
+            .filter(workspace::user_id.eq(user_id))
+            .filter(workspace::deleted_at.is_null())
             .set((workspace::deleted_at.eq(Some(now())),))
             .execute(conn)
     }
 
+    pub fn read_trashed_by_user(
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Vec<Workspace>, diesel::result::Error> {
+        workspace::table
+            .filter(workspace::user_id.eq(user_id))
+            .filter(workspace::deleted_at.is_not_null())
+            .order((workspace::deleted_at.desc(), workspace::id))
+            .load::<Workspace>(conn)
+    }
+
+    pub fn read_trashed_by_id(
+        conn: &mut PgConnection,
+        id: i32,
+    ) -> Result<Workspace, diesel::result::Error> {
+        workspace::table
+            .filter(workspace::id.eq(id))
+            .filter(workspace::deleted_at.is_not_null())
+            .first::<Workspace>(conn)
+    }
+
+    // the workspaces that went into the trash along with this one
+    fn trashed_with<'a>(&self, trashed: &'a [Workspace]) -> Vec<&'a Workspace> {
+        trashed
+            .iter()
+            .filter(|workspace| {
+                workspace.id != self.id
+                    && workspace.deleted_at == self.deleted_at
+                    && workspace.descends_from(trashed, self.id)
+            })
+            .collect()
+    }
+
+    /// Takes this workspace and its subtree back out of the trash. It goes
+    /// back under its old parent, or under the root when that parent is
+    /// gone, after the other children either way.
+    pub fn restore(&self, conn: &mut PgConnection) -> QueryResult<Workspace> {
+        conn.transaction(|conn| {
+            let trashed = Self::read_trashed_by_user(conn, self.user_id)?;
+            let ids: Vec<i32> = self
+                .trashed_with(&trashed)
+                .into_iter()
+                .map(|workspace| workspace.id)
+                .collect();
+
+            let parent_id = match Self::read_by_id(conn, self.parent_id) {
+                Ok(parent) => parent.id,
+                Err(diesel::result::Error::NotFound) => workspace::table
+                    .filter(workspace::user_id.eq(self.user_id))
+                    .filter(workspace::type_id.eq(WorkspaceType::Root as i32))
+                    .filter(workspace::deleted_at.is_null())
+                    .select(workspace::id)
+                    .first::<i32>(conn)?,
+                Err(e) => return Err(e),
+            };
+            let position = Self::next_position(conn, parent_id)?;
+
+            diesel::update(workspace::table)
+                .filter(workspace::id.eq_any(ids))
+                .set(workspace::deleted_at.eq(None::<NaiveDateTime>))
+                .execute(conn)?;
+            diesel::update(workspace::table)
+                .filter(workspace::id.eq(self.id))
+                .set((
+                    workspace::deleted_at.eq(None::<NaiveDateTime>),
+                    workspace::parent_id.eq(parent_id),
+                    workspace::position.eq(position),
+                ))
+                .get_result::<Workspace>(conn)
+        })
+    }
+
+    /// Permanently removes everything that was trashed before `before`,
+    /// along with its history.
+    pub fn purge_trash(conn: &mut PgConnection, before: NaiveDateTime) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            let expired = workspace::table
+                .filter(workspace::deleted_at.lt(before))
+                .select(workspace::id);
+
+            diesel::delete(workspace_revision::table)
+                .filter(workspace_revision::workspace_id.eq_any(expired))
+                .execute(conn)?;
+            diesel::delete(share_link::table)
+                .filter(share_link::workspace_id.eq_any(expired))
+                .execute(conn)?;
+            diesel::delete(workspace_member::table)
+                .filter(workspace_member::workspace_id.eq_any(expired))
+                .execute(conn)?;
+            diesel::delete(workspace::table)
+                .filter(workspace::deleted_at.lt(before))
+                .execute(conn)
+        })
+    }
+
+    pub fn delete_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/delete", self.id)} method="POST" id="delete-workspace"
+                onsubmit="return confirm('Move this workspace and its subworkspaces to the trash?')">
+                {csrf_input(csrf_token)}
+                <button type="submit" class="submit-delete">"✖ Delete"</button>
+            </form>
+        }
+    }
+
+    pub fn restore_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/trash/{}/restore", self.id)} method="POST">
+                {csrf_input(csrf_token)}
+                <button type="submit">"Restore"</button>
+            </form>
+        }
+    }
+
     pub fn to_rss_item(&self, author: String) -> rss::Item {
         rss::ItemBuilder::default()
             .title(Some(self.name.clone()))
@@ -259,14 +1083,107 @@ impl Workspace {
     }
 }
 
+/// Something the user deleted, with whatever went into the trash with it.
+#[derive(Clone)]
+pub struct TrashedWorkspace {
+    pub workspace: Workspace,
+    pub subtree: Vec<(usize, Workspace)>,
+}
+
+impl TrashedWorkspace {
+    pub fn read_by_user(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<Self>> {
+        let trashed = Workspace::read_trashed_by_user(conn, user_id)?;
+
+        // only what was deleted directly, its subtree is listed under it
+        Ok(trashed
+            .iter()
+            .filter(|workspace| {
+                !trashed.iter().any(|parent| {
+                    parent.id == workspace.parent_id && parent.deleted_at == workspace.deleted_at
+                })
+            })
+            .map(|workspace| {
+                let with = workspace.trashed_with(&trashed);
+                let mut subtree: Vec<Workspace> = vec![workspace.clone()];
+                subtree.extend(with.into_iter().cloned());
+
+                TrashedWorkspace {
+                    workspace: workspace.clone(),
+                    subtree: Workspace::tree_order_from(&subtree, workspace.id)
+                        .into_iter()
+                        .skip(1)
+                        .map(|(depth, workspace)| (depth, workspace.clone()))
+                        .collect(),
+                }
+            })
+            .collect())
+    }
+
+    pub fn to_html(&self, csrf_token: &str) -> String {
+        html! {
+            <li>
+                <strong>{escape_sanitized(&self.workspace.name)}</strong>
+                {format!(" deleted {}", self.workspace.deleted_at.unwrap_or_default().format("%Y-%m-%d %H:%M"))}
+                {self.workspace.restore_form(csrf_token)}
+                {if self.subtree.is_empty() {
+                    String::new()
+                } else {
+                    html! {
+                        <ul>
+                            {self.subtree
+                                .iter()
+                                .map(|(depth, workspace)| html! {
+                                    <li>{format!("{}{}", "— ".repeat(depth - 1), escape_sanitized(&workspace.name))}</li>
+                                })
+                                .collect::<String>()}
+                        </ul>
+                    }
+                }}
+            </li>
+        }
+    }
+}
+
+/// Who a workspace is rendered for, which decides what it may include.
+#[derive(Clone, Debug)]
+pub enum Audience {
+    Owner,
+    // someone else signed in, with everything they were let into
+    Member(i32, Vec<WorkspaceMember>),
+    Public,
+}
+
+impl Audience {
+    // whether drafts and private urls in `tree` may be shown to them
+    fn is_let_into(&self, workspace: &Workspace, tree: &[Workspace]) -> bool {
+        match self {
+            Audience::Owner => true,
+            Audience::Member(user_id, memberships) => {
+                Role::of(*user_id, workspace, tree, memberships).is_some()
+            }
+            Audience::Public => false,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct WorkspaceWithChildren {
     pub workspace: Workspace,
     pub children: Vec<Workspace>,
+    // the owner's whole hierarchy, for preludes, styles and rendering
+    pub tree: Vec<Workspace>,
+    // the part of it the signed in user was let into, for the sidebar and
+    // breadcrumbs, all of it for the owner
+    pub navigable: Vec<Workspace>,
+    pub author: User,
+    // what the signed in user may do here
+    pub role: Role,
+    // what they were let into, empty for the owner
+    pub memberships: Vec<WorkspaceMember>,
 }
 
 impl WorkspaceWithChildren {
-    pub fn subworkspaces(&self) -> String {
+    pub fn subworkspaces(&self, csrf_token: &str) -> String {
         let childs = if self.children.len() == 0 {
             html! {
                 <li>"No Subworkspaces yet"</li>
@@ -278,6 +1195,11 @@ impl WorkspaceWithChildren {
                     html! {
                         <li>
                             {workspace.link_to_self()}
+                            {if self.role >= Role::Editor {
+                                workspace.reorder_form(csrf_token)
+                            } else {
+                                String::new()
+                            }}
                         </li>
                     }
                 })
@@ -291,26 +1213,23 @@ impl WorkspaceWithChildren {
         }
     }
 
-    pub fn from_joined(joined: Vec<(Workspace, Option<Workspace>)>) -> Option<Self> {
+    // the workspace and its children, the rest comes from `for_user`
+    pub fn from_joined(joined: Vec<(Workspace, Option<Workspace>)>) -> Option<(Workspace, Vec<Workspace>)> {
         if joined.len() <= 0 {
             return None;
         };
 
-        let workspace_with_child = WorkspaceWithChildren {
-            workspace: joined[0].0.clone(),
-            children: vec![],
-        };
-        let workspace_with_child = joined
+        let workspace = joined[0].0.clone();
+        let children = joined
             .into_iter()
-            .filter(|(_, workspace)| workspace.is_some())
-            .fold(workspace_with_child, |mut acc, (_, workspace)| {
-                acc.children.push(workspace.unwrap());
-                acc
-            });
+            .filter_map(|(_, workspace)| workspace)
+            .collect();
 
-        Some(workspace_with_child)
+        Some((workspace, children))
     }
 
+    /// The workspace as `user_id` may see it, their own or one they were
+    /// let into, or none at all.
     pub fn read_by_user_and_id(
         conn: &mut PgConnection,
         user_id: i32,
@@ -318,18 +1237,24 @@ impl WorkspaceWithChildren {
     ) -> Result<Option<Self>, diesel::result::Error> {
         let (parent, children) = diesel::alias!(workspace as parent, workspace as children);
         parent
+            // trashed children are left out of the join, not the parent
             .left_join(
                 children.on(children
                     .field(workspace::parent_id)
-                    .eq(parent.field(workspace::id))),
+                    .eq(parent.field(workspace::id))
+                    .and(children.field(workspace::deleted_at).is_null())),
             )
             .filter(parent.field(workspace::deleted_at).is_null())
-            .filter(children.field(workspace::deleted_at).is_null())
-            .filter(parent.field(workspace::user_id).eq(user_id))
-            // .filter(children.field(workspace::user_id).eq(user_id)) or null?
             .filter(parent.field(workspace::id).eq(id))
+            .order((
+                children.field(workspace::position),
+                children.field(workspace::id),
+            ))
             .load::<(Workspace, Option<Workspace>)>(conn)
-            .map(|res| Self::from_joined(res))
+            .map(Self::from_joined)?
+            .map(|joined| Self::for_user(conn, user_id, joined))
+            .transpose()
+            .map(Option::flatten)
     }
 
     pub fn read_root_by_user(
@@ -339,18 +1264,99 @@ impl WorkspaceWithChildren {
         // workspace::table.first::<Workspace>(conn)
         let (parent, children) = diesel::alias!(workspace as parent, workspace as children);
         parent
+            // trashed children are left out of the join, not the parent
             .left_join(
                 children.on(children
                     .field(workspace::parent_id)
-                    .eq(parent.field(workspace::id))),
+                    .eq(parent.field(workspace::id))
+                    .and(children.field(workspace::deleted_at).is_null())),
             )
             .filter(parent.field(workspace::deleted_at).is_null())
-            .filter(children.field(workspace::deleted_at).is_null())
             .filter(parent.field(workspace::user_id).eq(user_id))
             // .filter(children.field(workspace::user_id).eq(user_id)) or null?
             .filter(parent.field(workspace::parent_id).eq(-1))
+            .order((
+                children.field(workspace::position),
+                children.field(workspace::id),
+            ))
             .load::<(Workspace, Option<Workspace>)>(conn)
-            .map(|res| Self::from_joined(res))
+            .map(Self::from_joined)?
+            .map(|joined| Self::for_user(conn, user_id, joined))
+            .transpose()
+            .map(Option::flatten)
+    }
+
+    // fills in the owner's tree and what of it `user_id` may get to
+    fn for_user(
+        conn: &mut PgConnection,
+        user_id: i32,
+        (workspace, children): (Workspace, Vec<Workspace>),
+    ) -> QueryResult<Option<Self>> {
+        let tree = Workspace::read_tree_by_user(conn, workspace.user_id)?;
+        let memberships = if workspace.is_owned_by(user_id) {
+            vec![]
+        } else {
+            WorkspaceMember::read_by_user(conn, user_id)?
+        };
+        let Some(role) = Role::of(user_id, &workspace, &tree, &memberships) else {
+            return Ok(None);
+        };
+
+        let navigable = tree
+            .iter()
+            .filter(|other| Role::of(user_id, other, &tree, &memberships).is_some())
+            .cloned()
+            .collect();
+        let children = children
+            .into_iter()
+            .filter(|child| Role::of(user_id, child, &tree, &memberships).is_some())
+            .collect();
+        let author = User::read_by_id(conn, workspace.user_id)?;
+
+        Ok(Some(WorkspaceWithChildren {
+            workspace,
+            children,
+            tree,
+            navigable,
+            author,
+            role,
+            memberships,
+        }))
+    }
+
+    pub fn tree_html(&self) -> String {
+        Workspace::tree_html(&self.navigable, &self.workspace, Workspace::link_to_self)
+    }
+
+    pub fn preludes(&self) -> Vec<Source> {
+        self.workspace.preludes(&self.author, &self.tree)
+    }
+
+    /// Who `user_id` is here, for rendering it, the owner only when it's
+    /// theirs.
+    pub fn audience(&self, user_id: i32) -> Audience {
+        if self.workspace.is_owned_by(user_id) {
+            Audience::Owner
+        } else {
+            Audience::Member(user_id, self.memberships.clone())
+        }
+    }
+
+    pub fn stylesheets(&self) -> Vec<String> {
+        self.workspace.stylesheets(&self.author, &self.tree)
+    }
+
+    pub fn breadcrumbs(&self) -> String {
+        self.workspace
+            .breadcrumbs(&self.navigable, Workspace::link_to_self)
+    }
+
+    pub fn actions(&self, csrf_token: &str, is_editing: bool) -> String {
+        self.workspace.actions(self.role, csrf_token, is_editing)
+    }
+
+    pub fn link_to_public_preview(&self) -> String {
+        self.workspace.link_to_public_preview(&self.author.username, &self.tree)
     }
 }
 
@@ -369,15 +1375,20 @@ pub struct EditWorkspaceApi {
     pub name: String,
     pub description: String,
     pub content: Option<String>,
+    pub prelude: Option<String>,
+    pub style: Option<String>,
 }
 
+// an empty prelude or stylesheet is no prelude or stylesheet, and is inherited
 #[derive(AsChangeset)]
-#[diesel(table_name = workspace)]
+#[diesel(table_name = workspace, treat_none_as_null = true)]
 pub struct EditWorkspace {
     pub name: String,
     pub description: String,
     pub content: Option<String>,
     pub updated_at: Option<NaiveDateTime>,
+    pub prelude: Option<String>,
+    pub style: Option<String>,
 }
 
 impl From<EditWorkspaceApi> for EditWorkspace {
@@ -385,21 +1396,99 @@ impl From<EditWorkspaceApi> for EditWorkspace {
         EditWorkspace {
             name: sanitize_html(&ws.name),
             description: sanitize_html(&ws.description),
-            content: Some(sanitize_html(&ws.content.unwrap_or(String::from("")))),
+            // kept as written, the output is what gets sanitized
+            content: Some(ws.content.unwrap_or_default()),
             updated_at: Some(now()),
+            prelude: ws.prelude.filter(|prelude| !prelude.trim().is_empty()),
+            style: ws
+                .style
+                .map(|style| sanitize_css(&style))
+                .filter(|style| !style.trim().is_empty()),
         }
     }
 }
 
 impl EditWorkspaceApi {
-    pub fn update(self, conn: &mut PgConnection, id: i32) -> QueryResult<usize> {
-        diesel::update(workspace::table)
-            .set::<EditWorkspace>(self.into())
-            .filter(workspace::id.eq(id))
-            .execute(conn)
+    pub fn preview(self, mut workspace: Workspace) -> Workspace {
+        let edit: EditWorkspace = self.into();
+        workspace.name = edit.name;
+        workspace.description = edit.description;
+        workspace.content = edit.content;
+        workspace.updated_at = edit.updated_at;
+        workspace.prelude = edit.prelude;
+        workspace.style = edit.style;
+        workspace
+    }
+
+    pub fn update(self, conn: &mut PgConnection, id: i32, user_id: i32) -> QueryResult<Workspace> {
+        conn.transaction(|conn| {
+            let workspace = diesel::update(workspace::table)
+                .set((
+                    EditWorkspace::from(self),
+                    workspace::rendered_html.eq(None::<String>),
+                    workspace::rendered_hash.eq(None::<String>),
+                ))
+                .filter(workspace::id.eq(id))
+                .get_result::<Workspace>(conn)?;
+
+            NewWorkspaceRevision::new(&workspace, user_id).insert(conn)?;
+            Ok(workspace)
+        })
+    }
+}
+
+//
+// Move Workspace
+//
+#[derive(Deserialize)]
+pub struct MoveWorkspaceApi {
+    pub parent_id: i32,
+}
+
+impl MoveWorkspaceApi {
+    /// Lands `workspace` after the new parent's other children, and tells
+    /// whether it could, as nothing goes inside of itself. The owner's tree
+    /// stays locked from that check to the move, so two moves at once can't
+    /// each make half of a cycle.
+    pub fn move_to(self, conn: &mut PgConnection, workspace: &Workspace) -> QueryResult<bool> {
+        conn.transaction(|conn| {
+            let workspaces = Workspace::lock_by_user(conn, workspace.user_id)?;
+            let parent = workspaces
+                .iter()
+                .find(|parent| parent.id == self.parent_id)
+                .ok_or(diesel::result::Error::NotFound)?;
+            if parent.id == workspace.id || parent.descends_from(&workspaces, workspace.id) {
+                return Ok(false);
+            }
+
+            let position = Workspace::next_position(conn, self.parent_id)?;
+            diesel::update(workspace::table)
+                .filter(workspace::id.eq(workspace.id))
+                .set((
+                    workspace::parent_id.eq(self.parent_id),
+                    workspace::position.eq(position),
+                ))
+                .execute(conn)?;
+            Ok(true)
+        })
     }
 }
 
+//
+// Reorder Workspace
+//
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Deserialize)]
+pub struct ReorderWorkspaceApi {
+    pub direction: Direction,
+}
+
 //
 // Publish Workspace
 //
@@ -455,8 +1544,8 @@ impl NewWorkspace {
             created_at: now(),
             updated_at: None,
             deleted_at: None,
-            name: new_workspace.name,
-            description: new_workspace.description,
+            name: sanitize_html(&new_workspace.name),
+            description: sanitize_html(&new_workspace.description),
             type_id: new_workspace.type_id,
             content: Some(String::from(DEFAULT_WORKSPACE_CONTENT)),
             parent_id,
@@ -465,6 +1554,19 @@ impl NewWorkspace {
     }
 
     pub fn insert(&self, conn: &mut PgConnection) -> Result<Workspace, diesel::result::Error> {
-        Workspace::new(conn, self)
+        self.insert_by(conn, self.user_id)
+    }
+
+    // for when the one adding it is not the owner of the tree
+    pub fn insert_by(&self, conn: &mut PgConnection, author_user_id: i32) -> Result<Workspace, diesel::result::Error> {
+        conn.transaction(|conn| {
+            // new workspaces go after their siblings
+            let position = Workspace::next_position(conn, self.parent_id)?;
+            let workspace = diesel::insert_into(workspace::table)
+                .values((self, workspace::position.eq(position)))
+                .get_result::<Workspace>(conn)?;
+            NewWorkspaceRevision::new(&workspace, author_user_id).insert(conn)?;
+            Ok(workspace)
+        })
     }
 }
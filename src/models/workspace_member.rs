@@ -0,0 +1,287 @@
+use crate::{
+    models::{session::csrf_input, workspace::Workspace},
+    schema::{user, workspace, workspace_member},
+    utils::now,
+};
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+use html_to_string_macro::html;
+use serde::Deserialize;
+use std::fmt::{self, Display};
+
+/// What someone may do with a workspace and everything under it, each
+/// role allowing all that the ones before it do. Owners aren't members,
+/// they can always do everything with their own tree.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // reads it and its history
+    Viewer,
+    // edits, publishes, reorders and adds subworkspaces
+    Editor,
+    // moves, deletes, shares and invites
+    Admin,
+    #[serde(skip_deserializing)]
+    Owner,
+}
+
+impl Role {
+    pub fn from_name(name: &str) -> Role {
+        match name {
+            "admin" => Role::Admin,
+            "editor" => Role::Editor,
+            _ => Role::Viewer,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+
+    /// The role `user_id` has on `workspace`, found in the owner's `tree`.
+    /// Membership of a workspace carries down to everything under it, so
+    /// the highest role granted on it or any of its ancestors wins.
+    pub fn of(
+        user_id: i32,
+        workspace: &Workspace,
+        tree: &[Workspace],
+        memberships: &[WorkspaceMember],
+    ) -> Option<Role> {
+        if workspace.is_owned_by(user_id) {
+            return Some(Role::Owner);
+        }
+
+        let granted_on: Vec<i32> = workspace
+            .ancestors(tree)
+            .into_iter()
+            .map(|ancestor| ancestor.id)
+            .chain(std::iter::once(workspace.id))
+            .collect();
+        memberships
+            .iter()
+            .filter(|member| member.user_id == user_id && granted_on.contains(&member.workspace_id))
+            .map(WorkspaceMember::role)
+            .max()
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Role::Viewer => "Viewer",
+                Role::Editor => "Editor",
+                Role::Admin => "Admin",
+                Role::Owner => "Owner",
+            }
+        )
+    }
+}
+
+/// Another user let into a workspace by its owner or one of its admins.
+#[derive(Clone, Debug, Identifiable, Selectable, Queryable)]
+#[diesel(table_name = workspace_member)]
+pub struct WorkspaceMember {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub role: String,
+    pub invited_by: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl WorkspaceMember {
+    pub fn read_by_user(
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        workspace_member::table
+            .filter(workspace_member::user_id.eq(user_id))
+            .load::<Self>(conn)
+    }
+
+    // only those let in here, not the ones let into an ancestor
+    pub fn read_by_workspace(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+    ) -> Result<Vec<MemberWithUser>, diesel::result::Error> {
+        workspace_member::table
+            .inner_join(user::table)
+            .filter(workspace_member::workspace_id.eq(workspace_id))
+            .filter(user::deleted_at.is_null())
+            .order(workspace_member::id)
+            .select((Self::as_select(), user::username))
+            .load::<(Self, String)>(conn)
+            .map(|res| {
+                res.into_iter()
+                    .map(|(member, username)| MemberWithUser { member, username })
+                    .collect()
+            })
+    }
+
+    pub fn read_by_workspace_and_id(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+        id: i32,
+    ) -> Result<Self, diesel::result::Error> {
+        workspace_member::table
+            .filter(workspace_member::workspace_id.eq(workspace_id))
+            .filter(workspace_member::id.eq(id))
+            .first::<Self>(conn)
+    }
+
+    /// Everything let into directly, with who owns it and the role given.
+    pub fn read_shared_with(
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Vec<SharedWorkspace>, diesel::result::Error> {
+        workspace_member::table
+            .inner_join(workspace::table)
+            .inner_join(user::table.on(user::id.eq(workspace::user_id)))
+            .filter(workspace_member::user_id.eq(user_id))
+            .filter(workspace::deleted_at.is_null())
+            .filter(user::deleted_at.is_null())
+            .order((user::username, workspace::name))
+            .select((Workspace::as_select(), user::username, workspace_member::role))
+            .load::<(Workspace, String, String)>(conn)
+            .map(|res| {
+                res.into_iter()
+                    .map(|(workspace, owner, role)| SharedWorkspace {
+                        workspace,
+                        owner,
+                        role: Role::from_name(&role),
+                    })
+                    .collect()
+            })
+    }
+
+    /// The role `user_id` has on `workspace`, or none when it's not theirs
+    /// and nobody let them in.
+    pub fn role_of(
+        conn: &mut PgConnection,
+        user_id: i32,
+        workspace: &Workspace,
+    ) -> Result<Option<Role>, diesel::result::Error> {
+        if workspace.is_owned_by(user_id) {
+            return Ok(Some(Role::Owner));
+        }
+
+        let tree = Workspace::read_tree_by_user(conn, workspace.user_id)?;
+        let memberships = Self::read_by_user(conn, user_id)?;
+        Ok(Role::of(user_id, workspace, &tree, &memberships))
+    }
+
+    pub fn role(&self) -> Role {
+        Role::from_name(&self.role)
+    }
+
+    pub fn remove(&self, conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        diesel::delete(workspace_member::table)
+            .filter(workspace_member::id.eq(self.id))
+            .execute(conn)
+    }
+
+    pub fn remove_form(&self, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/members/{}/remove", self.workspace_id, self.id)} method="POST">
+                {csrf_input(csrf_token)}
+                <button type="submit">"Remove"</button>
+            </form>
+        }
+    }
+
+    pub fn new_form(workspace_id: i32, csrf_token: &str) -> String {
+        html! {
+            <form action={format!("/workspace/{}/members", workspace_id)} method="POST">
+                {csrf_input(csrf_token)}
+                <label>
+                    <span>"Username"</span>
+                    <input type="text" name="username" required />
+                </label>
+                <label>
+                    <span>"Role"</span>
+                    <select name="role">
+                        <option value="viewer">"Viewer"</option>
+                        <option value="editor" selected>"Editor"</option>
+                        <option value="admin">"Admin"</option>
+                    </select>
+                </label>
+                <button type="submit">"Invite"</button>
+            </form>
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemberWithUser {
+    pub member: WorkspaceMember,
+    pub username: String,
+}
+
+/// A workspace someone else owns that the user was let into.
+#[derive(Clone, Debug)]
+pub struct SharedWorkspace {
+    pub workspace: Workspace,
+    pub owner: String,
+    pub role: Role,
+}
+
+impl SharedWorkspace {
+    pub fn to_html(&self) -> String {
+        html! {
+            <li>
+                {self.workspace.link_to_self()}
+                {format!(" from {}, as {}", self.owner, self.role.name())}
+            </li>
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewWorkspaceMemberApi {
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workspace_member)]
+pub struct NewWorkspaceMember {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub role: String,
+    pub invited_by: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl NewWorkspaceMember {
+    pub fn new(workspace_id: i32, user_id: i32, role: Role, invited_by: i32) -> Self {
+        NewWorkspaceMember {
+            workspace_id,
+            user_id,
+            role: role.name().to_string(),
+            invited_by,
+            created_at: now(),
+        }
+    }
+
+    // inviting someone who is already a member changes their role
+    pub fn insert(&self, conn: &mut PgConnection) -> Result<WorkspaceMember, diesel::result::Error> {
+        diesel::insert_into(workspace_member::table)
+            .values(self)
+            .on_conflict((workspace_member::workspace_id, workspace_member::user_id))
+            .do_update()
+            .set((
+                workspace_member::role.eq(self.role.clone()),
+                workspace_member::updated_at.eq(Some(now())),
+            ))
+            .get_result(conn)
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    models::workspace::Workspace,
+    schema::{user, workspace, workspace_revision},
+    utils::{escape_html, now},
+};
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+use html_to_string_macro::html;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Clone, Debug, Identifiable, Selectable, Queryable)]
+#[diesel(table_name = workspace_revision)]
+pub struct WorkspaceRevision {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub description: String,
+    pub content: Option<String>,
+    pub restored_from: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl WorkspaceRevision {
+    pub fn new(
+        conn: &mut PgConnection,
+        new_revision: &NewWorkspaceRevision,
+    ) -> Result<Self, diesel::result::Error> {
+        diesel::insert_into(workspace_revision::table)
+            .values(new_revision)
+            .get_result(conn)
+    }
+
+    pub fn read_by_workspace(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+    ) -> Result<Vec<RevisionWithAuthor>, diesel::result::Error> {
+        workspace_revision::table
+            .inner_join(user::table)
+            .filter(workspace_revision::workspace_id.eq(workspace_id))
+            .order(workspace_revision::id.desc())
+            .select((Self::as_select(), user::username))
+            .load::<(Self, String)>(conn)
+            .map(|res| {
+                res.into_iter()
+                    .map(|(revision, author)| RevisionWithAuthor { revision, author })
+                    .collect()
+            })
+    }
+
+    pub fn read_by_workspace_and_id(
+        conn: &mut PgConnection,
+        workspace_id: i32,
+        id: i32,
+    ) -> Result<Self, diesel::result::Error> {
+        workspace_revision::table
+            .filter(workspace_revision::workspace_id.eq(workspace_id))
+            .filter(workspace_revision::id.eq(id))
+            .first::<Self>(conn)
+    }
+
+    /// Puts this revision back on the workspace. History is never rewritten,
+    /// the restore is recorded as a brand new revision pointing back here.
+    pub fn restore(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+    ) -> Result<Self, diesel::result::Error> {
+        conn.transaction(|conn| {
+            let workspace = diesel::update(workspace::table)
+                .filter(workspace::id.eq(self.workspace_id))
+                .set((
+                    workspace::name.eq(self.name.clone()),
+                    workspace::description.eq(self.description.clone()),
+                    workspace::content.eq(self.content.clone()),
+                    workspace::updated_at.eq(Some(now())),
+                    workspace::rendered_html.eq(None::<String>),
+                    workspace::rendered_hash.eq(None::<String>),
+                ))
+                .get_result::<Workspace>(conn)?;
+
+            let mut new_revision = NewWorkspaceRevision::new(&workspace, user_id);
+            new_revision.restored_from = Some(self.id);
+            new_revision.insert(conn)
+        })
+    }
+
+    pub fn diff(&self, other: &WorkspaceRevision) -> String {
+        let from = self.content.clone().unwrap_or_default();
+        let to = other.content.clone().unwrap_or_default();
+
+        let lines = TextDiff::from_lines(&from, &to)
+            .iter_all_changes()
+            .map(|change| {
+                let (class, sign) = match change.tag() {
+                    ChangeTag::Delete => ("diff-delete", "-"),
+                    ChangeTag::Insert => ("diff-insert", "+"),
+                    ChangeTag::Equal => ("diff-equal", " "),
+                };
+                html! {
+                    <li class={class}><span class="diff-sign">{sign}</span>{escape_html(change.value().trim_end_matches('\n'))}</li>
+                }
+            })
+            .collect::<String>();
+
+        html! {
+            <ol class="diff">
+                {lines}
+            </ol>
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RevisionWithAuthor {
+    pub revision: WorkspaceRevision,
+    pub author: String,
+}
+
+#[derive(Deserialize)]
+pub struct DiffRevisionsApi {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workspace_revision)]
+pub struct NewWorkspaceRevision {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub description: String,
+    pub content: Option<String>,
+    pub restored_from: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl NewWorkspaceRevision {
+    pub fn new(workspace: &Workspace, author_user_id: i32) -> Self {
+        NewWorkspaceRevision {
+            workspace_id: workspace.id,
+            user_id: author_user_id,
+            name: workspace.name.clone(),
+            description: workspace.description.clone(),
+            content: workspace.content.clone(),
+            restored_from: None,
+            created_at: now(),
+        }
+    }
+
+    pub fn insert(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<WorkspaceRevision, diesel::result::Error> {
+        WorkspaceRevision::new(conn, self)
+    }
+}
+
+#[test]
+fn test_diff_marks_changed_lines() {
+    let revision = |content: &str| WorkspaceRevision {
+        id: 1,
+        workspace_id: 1,
+        user_id: 1,
+        name: String::from("name"),
+        description: String::from("description"),
+        content: Some(content.to_string()),
+        restored_from: None,
+        created_at: now(),
+    };
+
+    let diff = revision("# Title\nold line\n").diff(&revision("# Title\nnew <b>line</b>\n"));
+
+    assert!(diff.contains(r#"<li class="diff-equal"><span class="diff-sign"> </span># Title</li>"#));
+    assert!(diff.contains(r#"<li class="diff-delete"><span class="diff-sign">-</span>old line</li>"#));
+    assert!(diff.contains(
+        r#"<li class="diff-insert"><span class="diff-sign">+</span>new &lt;b&gt;line&lt;/b&gt;</li>"#
+    ));
+}
@@ -0,0 +1,568 @@
+use crate::{render_cache::RenderCache, sandbox, utils::escape_html};
+use bebop_lang::{
+    lisp::{env::Lenv, Lerr, Lval},
+    markdown::{lisp::markdown_to_lisp, parser::parse_markdown, Markdown},
+};
+use html_to_string_macro::html;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+// how many workspaces deep `include` may go, counting the one being rendered
+const MAX_INCLUDE_DEPTH: usize = 4;
+
+/// A named piece of bebop that runs ahead of a workspace, like a prelude.
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub name: String,
+    pub code: String,
+}
+
+impl Source {
+    pub fn new(name: &str, code: &str) -> Self {
+        Source {
+            name: name.to_string(),
+            code: code.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    // what the line and column count from, the content or a prelude
+    pub source: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(source: &str, message: String) -> Self {
+        Diagnostic {
+            source: source.to_string(),
+            line: None,
+            column: None,
+            message,
+        }
+    }
+
+    fn at(mut self, text: &str, offset: usize, with_column: bool) -> Self {
+        let (line, column) = line_and_column(text, offset);
+        self.line = Some(line);
+        self.column = if with_column { Some(column) } else { None };
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ", line {}", line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ", column {}", column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderResult {
+    pub html: String,
+    pub diagnostics: Vec<Diagnostic>,
+    // workspaces pulled in with `include`, their output can change under us
+    pub includes: Vec<i32>,
+}
+
+impl RenderResult {
+    pub fn failed(diagnostic: Diagnostic) -> Self {
+        RenderResult {
+            html: String::new(),
+            diagnostics: vec![diagnostic],
+            includes: vec![],
+        }
+    }
+
+    pub fn diagnostics_html(&self) -> String {
+        if self.diagnostics.is_empty() {
+            return String::new();
+        }
+
+        html! {
+            <ul class="diagnostics">
+                {self.diagnostics
+                    .iter()
+                    .map(|diagnostic| html! {
+                        <li class="error">{escape_html(&diagnostic.to_string())}</li>
+                    })
+                    .collect::<String>()}
+            </ul>
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Segment {
+    Prelude(usize),
+    Values,
+    Block(usize),
+}
+
+/// Finds the workspace `(include id)` refers to, as a program ready to run,
+/// or why it can't be included.
+pub type Resolver = Arc<dyn Fn(i32) -> Result<Program, String> + Send + Sync>;
+
+// what is being included right now on this thread, outermost first
+struct Inclusion {
+    resolve: Resolver,
+    stack: Vec<i32>,
+    included: Vec<i32>,
+}
+
+thread_local! {
+    static INCLUSION: RefCell<Option<Inclusion>> = const { RefCell::new(None) };
+}
+
+/// What a program is compiled from. Cheap to put together, so that is done
+/// before going to the sandbox, while compiling the markdown happens in it.
+pub struct ProgramText {
+    pub preludes: Vec<Source>,
+    pub values: String,
+    pub content: String,
+}
+
+impl ProgramText {
+    /// What the output depends on, the same for programs that render alike
+    /// unless they include other workspaces.
+    pub fn key(&self) -> String {
+        let mut parts: Vec<&str> = self
+            .preludes
+            .iter()
+            .map(|prelude| prelude.code.as_str())
+            .collect();
+        parts.push(&self.values);
+        parts.push(&self.content);
+        RenderCache::key(&parts)
+    }
+
+    pub fn compile(self) -> Program {
+        Program::new(self.preludes, self.values, self.content)
+    }
+}
+
+/// A workspace's markdown compiled to bebop, remembering which prelude or
+/// markdown block each part of the program came from so errors can be
+/// pointed back at the text the user actually wrote.
+pub struct Program {
+    preludes: Vec<Source>,
+    values: String,
+    content: String,
+    lisp: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    includes: Option<(i32, Resolver)>,
+}
+
+impl Program {
+    pub fn new(preludes: Vec<Source>, values: String, content: String) -> Self {
+        let mut diagnostics = vec![];
+
+        let (rest, lisp) = match parse_markdown(&content) {
+            Ok((rest, markdown)) => (
+                rest.len(),
+                markdown.into_iter().map(markdown_to_lisp).collect(),
+            ),
+            Err(_) => (content.len(), vec![]),
+        };
+        if !content[content.len() - rest..].trim().is_empty() {
+            diagnostics.push(
+                Diagnostic::new(
+                    "content",
+                    String::from(
+                        "Could not read the markdown from here on, the rest of the page was left out",
+                    ),
+                )
+                .at(&content, content.len() - rest, true),
+            );
+        }
+
+        Program {
+            preludes,
+            values,
+            content,
+            lisp,
+            diagnostics,
+            includes: None,
+        }
+    }
+
+    /// Lets the program `(include id)` other workspaces, found with
+    /// `resolve`. `id` is the workspace this program belongs to, so it
+    /// can't include itself.
+    pub fn with_includes(mut self, id: i32, resolve: Resolver) -> Self {
+        self.includes = Some((id, resolve));
+        self
+    }
+
+    // the whole program, with where each segment of it starts
+    fn text(&self) -> (String, Vec<(usize, Segment)>) {
+        let mut text = String::new();
+        let mut segments = vec![];
+
+        for (i, prelude) in self.preludes.iter().enumerate() {
+            segments.push((text.len(), Segment::Prelude(i)));
+            text.push_str(&prelude.code);
+            text.push('\n');
+        }
+
+        segments.push((text.len(), Segment::Values));
+        text.push_str(&self.values);
+        text.push('\n');
+
+        for (i, lisp) in self.lisp.iter().enumerate() {
+            segments.push((text.len(), Segment::Block(i)));
+            text.push_str(lisp);
+        }
+        text.push('\n');
+
+        (text, segments)
+    }
+
+    pub fn run(mut self) -> RenderResult {
+        // an included program runs within the inclusion already going
+        let outermost = match self.includes.take() {
+            Some((id, resolve)) => INCLUSION.with(|inclusion| {
+                let mut inclusion = inclusion.borrow_mut();
+                if inclusion.is_some() {
+                    return false;
+                }
+                *inclusion = Some(Inclusion {
+                    resolve,
+                    stack: vec![id],
+                    included: vec![],
+                });
+                true
+            }),
+            None => false,
+        };
+
+        let mut result = self.execute();
+
+        if outermost {
+            if let Some(inclusion) = INCLUSION.with(|inclusion| inclusion.borrow_mut().take()) {
+                result.includes = inclusion.included;
+            }
+        }
+        result
+    }
+
+    fn execute(mut self) -> RenderResult {
+        let (text, segments) = self.text();
+        tracing::info!("{}", text);
+
+        let mut env = program_env();
+        let html = match sandbox::parse(&text) {
+            Ok(exprs) => match sandbox::eval(&mut env, Lval::Sexpr(exprs)) {
+                Ok(Lval::Str(html)) => html,
+                Ok(other) => format!("{:?}", other),
+                Err(message) => {
+                    let diagnostic = self.locate_eval_error(&text, &segments, message);
+                    self.diagnostics.push(diagnostic);
+                    String::new()
+                }
+            },
+            Err(offset) => {
+                let diagnostic = self.locate_parse_error(&text, &segments, offset);
+                self.diagnostics.push(diagnostic);
+                String::new()
+            }
+        };
+
+        RenderResult {
+            html,
+            diagnostics: self.diagnostics,
+            includes: vec![],
+        }
+    }
+
+    fn locate_parse_error(
+        &self,
+        text: &str,
+        segments: &[(usize, Segment)],
+        offset: usize,
+    ) -> Diagnostic {
+        let snippet: String = text[offset..]
+            .trim_start()
+            .chars()
+            .take_while(|c| *c != '\n')
+            .take(32)
+            .collect();
+        let message = format!("Could not parse the expression starting at `{}`", snippet);
+
+        let (start, segment) = segments
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .copied()
+            .unwrap_or((0, Segment::Values));
+
+        match segment {
+            Segment::Prelude(i) => Diagnostic::new(&self.preludes[i].name, message).at(
+                &self.preludes[i].code,
+                offset - start,
+                true,
+            ),
+            Segment::Values => Diagnostic::new("workspace details", message),
+            Segment::Block(i) => {
+                let markdown = self.markdown();
+                let block_start = self.block_starts(&markdown)[i];
+                match &markdown[i] {
+                    // inline lisp is copied verbatim, so the column holds too
+                    Markdown::Lisp(code) => match self.content[block_start..].find(code.as_str()) {
+                        Some(code_start) => Diagnostic::new("content", message).at(
+                            &self.content,
+                            block_start + code_start + offset - start,
+                            true,
+                        ),
+                        None => Diagnostic::new("content", message).at(
+                            &self.content,
+                            block_start,
+                            false,
+                        ),
+                    },
+                    _ => Diagnostic::new("content", message).at(&self.content, block_start, false),
+                }
+            }
+        }
+    }
+
+    // Bebop errors carry no position. The whole program is one big concat,
+    // so replaying it a top level expression at a time in a fresh env finds
+    // the first one that fails, or the first one that isn't text.
+    fn locate_eval_error(
+        &self,
+        text: &str,
+        segments: &[(usize, Segment)],
+        message: String,
+    ) -> Diagnostic {
+        let mut env = program_env();
+        let mut culprit = None;
+        let mut first = true;
+
+        'segments: for (i, (start, segment)) in segments.iter().enumerate() {
+            let end = segments
+                .get(i + 1)
+                .map(|(end, _)| *end)
+                .unwrap_or(text.len());
+            let exprs = match sandbox::parse(&text[*start..end]) {
+                Ok(exprs) => exprs,
+                // only parses as part of its neighbours, can't say more
+                Err(_) => break,
+            };
+
+            for expr in exprs {
+                match sandbox::eval(&mut env, expr) {
+                    Err(_) => {
+                        culprit = Some(*segment);
+                        break 'segments;
+                    }
+                    // the leading concat is the only thing allowed not to be text
+                    Ok(Lval::Str(_)) => (),
+                    Ok(_) if first => (),
+                    Ok(_) => {
+                        culprit = culprit.or(Some(*segment));
+                    }
+                }
+                first = false;
+            }
+        }
+
+        match culprit {
+            Some(Segment::Prelude(i)) => Diagnostic::new(&self.preludes[i].name, message),
+            Some(Segment::Values) => Diagnostic::new("workspace details", message),
+            Some(Segment::Block(i)) => {
+                let block_start = self.block_starts(&self.markdown())[i];
+                Diagnostic::new("content", message).at(&self.content, block_start, false)
+            }
+            None => Diagnostic::new("program", message),
+        }
+    }
+
+    // markdown_to_lisp consumes the blocks, so they are read again when an
+    // error has to be pointed at one
+    fn markdown(&self) -> Vec<Markdown> {
+        parse_markdown(&self.content)
+            .map(|(_, markdown)| markdown)
+            .unwrap_or_default()
+    }
+
+    // The parser doesn't keep spans, so find where each block starts by
+    // reparsing from every line. A line starts block k when everything
+    // from it parses to exactly blocks k onwards. Only done when there is
+    // an error to report.
+    fn block_starts(&self, markdown: &[Markdown]) -> Vec<usize> {
+        let total = markdown.len();
+        let mut starts: Vec<Option<usize>> = vec![None; total];
+
+        let line_starts = std::iter::once(0).chain(
+            self.content
+                .match_indices('\n')
+                .map(|(i, _)| i + 1)
+                .filter(|i| *i < self.content.len()),
+        );
+        for line_start in line_starts {
+            if let Ok((_, md)) = parse_markdown(&self.content[line_start..]) {
+                if md.len() <= total && md[..] == markdown[total - md.len()..] {
+                    let k = total - md.len();
+                    starts[k] = starts[k].or(Some(line_start));
+                }
+            }
+        }
+
+        // blocks that start mid line take the line of the block before
+        let mut previous = 0;
+        starts
+            .into_iter()
+            .map(|start| {
+                previous = start.unwrap_or(previous);
+                previous
+            })
+            .collect()
+    }
+}
+
+fn program_env() -> Lenv {
+    let mut env = sandbox::init_env();
+    bebop_lang::lisp::add_builtin(&mut env, "include", include);
+    env
+}
+
+// `(include 42)` renders workspace 42 in place, in a fresh environment of
+// its own so the two programs' definitions don't mix.
+fn include(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let id = match operands.as_slice() {
+        [Lval::Num(id)] => *id as i32,
+        [Lval::Str(id)] => match id.trim().parse::<i32>() {
+            Ok(id) => id,
+            Err(_) => return fail(env, format!("include needs a workspace id, not \"{}\"", id)),
+        },
+        _ => return fail(env, String::from("include needs a single workspace id")),
+    };
+
+    let resolve = INCLUSION.with(|inclusion| {
+        let mut inclusion = inclusion.borrow_mut();
+        let inclusion = match inclusion.as_mut() {
+            Some(inclusion) => inclusion,
+            None => return Err(String::from("Workspaces can't be included here")),
+        };
+
+        if inclusion.stack.contains(&id) {
+            let cycle = inclusion
+                .stack
+                .iter()
+                .skip_while(|included| **included != id)
+                .chain(std::iter::once(&id))
+                .map(|included| included.to_string())
+                .collect::<Vec<String>>()
+                .join(" → ");
+            return Err(format!("Workspace {} includes itself: {}", id, cycle));
+        }
+        if inclusion.stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "Workspace {} is more than {} includes deep",
+                id,
+                MAX_INCLUDE_DEPTH - 1
+            ));
+        }
+
+        inclusion.stack.push(id);
+        if !inclusion.included.contains(&id) {
+            inclusion.included.push(id);
+        }
+        Ok(inclusion.resolve.clone())
+    });
+    let resolve = match resolve {
+        Ok(resolve) => resolve,
+        Err(message) => return fail(env, message),
+    };
+
+    // no borrow is held here, the included program may include more
+    let result = resolve(id).map(|program| program.run());
+    INCLUSION.with(|inclusion| {
+        if let Some(inclusion) = inclusion.borrow_mut().as_mut() {
+            inclusion.stack.pop();
+        }
+    });
+
+    match result {
+        Ok(result) => match result.diagnostics.first() {
+            Some(diagnostic) => fail(env, format!("In workspace {}, {}", id, diagnostic)),
+            None => Ok(Lval::Str(result.html)),
+        },
+        Err(message) => fail(env, message),
+    }
+}
+
+fn fail(env: &mut Lenv, message: String) -> Result<Lval, Lerr> {
+    Err(sandbox::error(env, message))
+}
+
+/// Writes `value` as a Bebop string literal. Bebop strings run to the next
+/// double quote and have no escapes, so the value is written as html, which
+/// is what programs make of it anyway, and can't close the string early.
+pub fn string_literal(value: &str) -> String {
+    format!("\"{}\"", escape_html(value))
+}
+
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+fn run_content(content: &str) -> RenderResult {
+    Program::new(
+        vec![Source::new("global prelude", crate::GLOBAL_PRELUDE)],
+        String::from(r#"(def [title] "title")"#),
+        content.to_string(),
+    )
+    .run()
+}
+
+#[test]
+fn test_program_reports_error_lines() {
+    let ok = run_content("# Hello\n\n|(p title)|\n");
+    assert!(ok.diagnostics.is_empty());
+    assert!(ok.html.contains("<p>title</p>"));
+
+    let unbound = run_content("# Hello\n\nsome text\n|(p nope)|\n");
+    assert_eq!(unbound.diagnostics.len(), 1);
+    assert_eq!(unbound.diagnostics[0].source, "content");
+    assert_eq!(unbound.diagnostics[0].line, Some(4));
+    assert!(unbound.diagnostics[0].message.contains("nope"));
+
+    let unparsed = run_content("# Hello\n|(p title) (p \"oops\"|\n");
+    assert_eq!(unparsed.diagnostics.len(), 1);
+    assert_eq!(unparsed.diagnostics[0].line, Some(2));
+    assert_eq!(unparsed.diagnostics[0].column, Some(12));
+}
+
+#[test]
+fn test_string_literal_keeps_values_whole() {
+    assert_eq!(string_literal("plain"), "\"plain\"");
+    assert_eq!(string_literal("say \"hi\""), "\"say &quot;hi&quot;\"");
+
+    let title = "a \"quoted\") (def [x] \"\\ back|slash |pipes|\nand <b>lines</b>";
+    let result = Program::new(
+        vec![Source::new("global prelude", crate::GLOBAL_PRELUDE)],
+        format!("(def [title] {})", string_literal(title)),
+        String::from("|(p title)|\n"),
+    )
+    .run();
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    assert!(result.html.contains(&format!("<p>{}</p>", escape_html(title))));
+}
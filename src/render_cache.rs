@@ -0,0 +1,65 @@
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::{num::NonZeroUsize, sync::Mutex};
+
+/// Rendered workspace html, keyed by a hash of everything that went into
+/// the program. Edits and prelude changes produce a new key, so stale
+/// entries are never served and simply age out.
+#[derive(Debug)]
+pub struct RenderCache {
+    entries: Mutex<LruCache<String, String>>,
+}
+
+impl RenderCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        RenderCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            // length prefix so moving text between parts changes the key
+            hasher.update(part.len().to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.lock().ok()?.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: String, html: String) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(key, html);
+        }
+    }
+}
+
+#[test]
+fn test_render_cache_evicts_least_recent() {
+    let cache = RenderCache::new(2);
+    let (a, b, c) = (
+        RenderCache::key(&["a", ""]),
+        RenderCache::key(&["", "a"]),
+        RenderCache::key(&["c"]),
+    );
+    assert_ne!(a, b);
+
+    cache.insert(a.clone(), String::from("<p>a</p>"));
+    cache.insert(b.clone(), String::from("<p>b</p>"));
+    assert_eq!(cache.get(&a), Some(String::from("<p>a</p>")));
+
+    cache.insert(c.clone(), String::from("<p>c</p>"));
+    assert_eq!(cache.get(&b), None);
+    assert_eq!(cache.get(&a), Some(String::from("<p>a</p>")));
+    assert_eq!(cache.get(&c), Some(String::from("<p>c</p>")));
+}
@@ -1,4 +1,4 @@
-use crate::{models::{self, user::ExpandedUser}, routes, Context, ServerError};
+use crate::{models::{self, user::ExpandedUser}, routes, Context, NotFound, ServerError};
 use warp::{filters::{self, BoxedFilter}, reject, Filter};
 
 pub fn feed() -> BoxedFilter<(
@@ -30,13 +30,86 @@ pub fn workspace() -> BoxedFilter<(
     models::feed::FeedWorkspace,
 )> {
     workspace_prefix()
-        .and(routes::user::authenticate_cookie())
-        .map(|s, id, context, expanded_user| (s, id, context, Some(expanded_user)))
-        .or(workspace_prefix().and(filters::ext::get::<Context>()).map(|s, id, c| (s, id, c, None)))
-        .unify()
+        .and(viewer())
+        .and_then(with_workspace)
         .untuple_one()
+        .and_then(with_role)
+        .untuple_one()
+        .boxed()
+}
+
+// a workspace's own stylesheet, along with the etag the browser already has
+pub fn workspace_stylesheet() -> BoxedFilter<(Context, String, Option<String>)> {
+    warp::path::param::<String>()
+        .and(warp::path("workspace"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("style.css"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(viewer())
         .and_then(with_workspace)
         .untuple_one()
+        .map(|context, _, workspace: models::feed::FeedWorkspace| {
+            (context, workspace.workspace.stylesheet())
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>("if-none-match"))
+        .boxed()
+}
+
+pub fn shared() -> BoxedFilter<(
+    Context,
+    Option<models::user::ExpandedUser>,
+    models::feed::FeedWorkspace,
+)> {
+    warp::path("s")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(viewer())
+        .and_then(with_shared_workspace)
+        .untuple_one()
+        .and_then(with_role)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn shared_stylesheet() -> BoxedFilter<(Context, String, Option<String>)> {
+    warp::path("s")
+        .and(warp::path::param::<String>())
+        .and(warp::path("style.css"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(viewer())
+        .and_then(with_shared_workspace)
+        .untuple_one()
+        .map(|context, _, workspace: models::feed::FeedWorkspace| {
+            (context, workspace.workspace.stylesheet())
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>("if-none-match"))
+        .boxed()
+}
+
+/// Anything under a name its user has since changed, sent on to the same
+/// place under their current one. Only ever temporarily, as the name can be
+/// taken back.
+pub fn renamed() -> BoxedFilter<(warp::http::Uri,)> {
+    warp::path::param::<String>()
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(filters::ext::get::<Context>())
+        .and_then(with_current_path)
+        .boxed()
+}
+
+// whoever is looking, signed in or not
+fn viewer() -> BoxedFilter<(Context, Option<ExpandedUser>)> {
+    routes::user::authenticate_cookie()
+        .map(|context, expanded_user| (context, Some(expanded_user)))
+        .or(filters::ext::get::<Context>().map(|context| (context, None)))
+        .unify()
+        .untuple_one()
         .boxed()
 }
 
@@ -59,17 +132,76 @@ async fn with_workspace(
         })
     })?;
 
-    if workspace.is_none() {
-        return Err(warp::reject());
-    };
-
-    let workspace = workspace.unwrap();
+    let workspace = workspace
+        .filter(|workspace| workspace.is_visible_to(expanded_user.as_ref()))
+        .ok_or(reject::custom(NotFound))?;
 
     tracing::info!("{:?}", workspace.user);
 
     Ok((context, expanded_user, workspace))
 }
 
+async fn with_current_path(
+    username: String,
+    tail: warp::path::Tail,
+    context: Context,
+) -> Result<warp::http::Uri, warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let user = models::username_redirect::UsernameRedirect::read_user(&mut conn, &username)
+        .map_err(|_| reject::not_found())?;
+
+    format!("/{}/{}", user.username, tail.as_str())
+        .parse::<warp::http::Uri>()
+        .map_err(|_| reject::not_found())
+}
+
+async fn with_shared_workspace(
+    token: String,
+    context: Context,
+    expanded_user: Option<ExpandedUser>,
+) -> Result<(
+    Context,
+    Option<ExpandedUser>,
+    models::feed::FeedWorkspace,
+), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = models::feed::FeedWorkspace::get_shared(&mut conn, &token)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?
+        .ok_or(reject::custom(NotFound))?;
+
+    Ok((context, expanded_user, workspace))
+}
+
+// what the one looking may do, only editors get to see why it didn't render
+async fn with_role(
+    context: Context,
+    expanded_user: Option<ExpandedUser>,
+    mut workspace: models::feed::FeedWorkspace,
+) -> Result<(
+    Context,
+    Option<ExpandedUser>,
+    models::feed::FeedWorkspace,
+), warp::Rejection> {
+    if let Some(expanded_user) = &expanded_user {
+        let mut conn = context.db_conn.get_conn();
+        workspace.role = workspace.role_of(&mut conn, expanded_user.user.id).map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+    }
+
+    Ok((context, expanded_user, workspace))
+}
+
 async fn with_feed(
     username: String,
     context: Context,
@@ -95,3 +227,201 @@ async fn with_feed(
 
     Ok((context, feed.unwrap()))
 }
+
+#[cfg(test)]
+async fn get_public(
+    context: &Context,
+    viewer: Option<&ExpandedUser>,
+    owner: &ExpandedUser,
+    id: i32,
+) -> Result<(Context, Option<ExpandedUser>, models::feed::FeedWorkspace), warp::Rejection> {
+    let request = warp::test::request()
+        .path(&format!("/{}/workspace/{}", owner.user.username, id))
+        .extension(context.clone());
+    match viewer {
+        Some(viewer) => request.header("cookie", routes::workspace::session_cookie(viewer)),
+        None => request,
+    }
+    .filter(&workspace())
+    .await
+}
+
+#[tokio::test]
+async fn test_drafts_are_hidden_from_the_public() {
+    use crate::routes::workspace::{child_workspace, signed_in_user, test_context};
+
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let published = child_workspace(&context, &alice, alice_root.id, "published");
+    let draft = child_workspace(&context, &alice, alice_root.id, "draft");
+    let under_draft = child_workspace(&context, &alice, draft.id, "under draft");
+    let deleted = child_workspace(&context, &alice, alice_root.id, "deleted");
+
+    let mut conn = context.db_conn.get_conn();
+    for id in [published.id, under_draft.id, deleted.id] {
+        models::workspace::PublishWorkspaceApi { is_published: true }
+            .publish(&mut conn, id)
+            .unwrap();
+    }
+    deleted.delete(&mut conn).unwrap();
+
+    for id in [alice_root.id, published.id] {
+        let (_, _, workspace) = get_public(&context, None, &alice, id).await.unwrap();
+        assert!(workspace.is_public());
+        get_public(&context, Some(&bob), &alice, id).await.unwrap();
+    }
+
+    for id in [draft.id, under_draft.id] {
+        let rejection = get_public(&context, None, &alice, id).await.err().expect("hidden");
+        assert!(rejection.find::<NotFound>().is_some());
+        let rejection = get_public(&context, Some(&bob), &alice, id).await.err().expect("hidden");
+        assert!(rejection.find::<NotFound>().is_some());
+
+        // the owner previews it as the public would see it
+        let (_, _, workspace) = get_public(&context, Some(&alice), &alice, id).await.unwrap();
+        assert!(!workspace.is_public());
+    }
+
+    assert!(get_public(&context, None, &alice, deleted.id).await.is_err());
+    assert!(get_public(&context, Some(&alice), &alice, deleted.id).await.is_err());
+
+    // a workspace is only public under its own author's name
+    assert!(get_public(&context, None, &bob, published.id).await.is_err());
+}
+
+#[tokio::test]
+async fn test_share_links() {
+    use crate::routes::workspace::{child_workspace, form_post, signed_in_user, test_context};
+
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let draft = child_workspace(&context, &alice, alice_root.id, "draft");
+
+    let share = routes::workspace::new_share_link();
+    let (_, _, _, links) = form_post(format!("/workspace/{}/share", draft.id), &alice, "expires_in_days=7")
+        .extension(context.clone())
+        .filter(&share)
+        .await
+        .unwrap();
+    assert_eq!(links.len(), 1);
+    let link = links[0].clone();
+    assert_eq!(link.token.len(), 64);
+    assert!(link.expires_at.is_some());
+
+    let rejection = form_post(format!("/workspace/{}/share", draft.id), &bob, "expires_in_days=0")
+        .extension(context.clone())
+        .filter(&share)
+        .await
+        .err()
+        .expect("bob can't share alice's draft");
+    assert!(rejection.find::<crate::NotAuthorized>().is_some());
+
+    let get_shared = |token: String| {
+        let context = context.clone();
+        async move {
+            warp::test::request()
+                .path(&format!("/s/{}", token))
+                .extension(context)
+                .filter(&shared())
+                .await
+        }
+    };
+
+    // no account needed, and the draft stays hidden everywhere else
+    let (_, viewer, workspace) = get_shared(link.token.clone()).await.unwrap();
+    assert!(viewer.is_none());
+    assert_eq!(workspace.workspace.id, draft.id);
+    assert!(workspace.share_link.is_some());
+    assert!(get_public(&context, None, &alice, draft.id).await.is_err());
+    assert!(get_shared(String::from("nope")).await.is_err());
+
+    let (_, _, _, links) = form_post(format!("/workspace/{}/share/{}/revoke", draft.id, link.id), &alice, "")
+        .extension(context.clone())
+        .filter(&routes::workspace::revoke_share_link())
+        .await
+        .unwrap();
+    assert!(links.is_empty());
+    let rejection = get_shared(link.token.clone()).await.err().expect("revoked");
+    assert!(rejection.find::<NotFound>().is_some());
+
+    let mut conn = context.db_conn.get_conn();
+    let expired = models::share_link::NewShareLink {
+        workspace_id: draft.id,
+        token: crate::utils::random_token(),
+        created_at: crate::utils::now(),
+        expires_at: Some(crate::utils::now() - chrono::Duration::minutes(1)),
+    }
+    .insert(&mut conn)
+    .unwrap();
+    assert!(expired.is_expired());
+    assert!(get_shared(expired.token).await.is_err());
+}
+
+#[tokio::test]
+async fn test_only_editors_see_diagnostics() {
+    use crate::routes::workspace::{child_workspace, form_post, signed_in_user, test_context};
+
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let broken = child_workspace(&context, &alice, alice_root.id, "broken");
+    form_post(
+        format!("/workspace/{}", broken.id),
+        &alice,
+        "name=broken&description=broken&content=|(p+nope)|%0A",
+    )
+    .extension(context.clone())
+    .filter(&routes::workspace::edit())
+    .await
+    .unwrap();
+    let mut conn = context.db_conn.get_conn();
+    models::workspace::PublishWorkspaceApi { is_published: true }
+        .publish(&mut conn, broken.id)
+        .unwrap();
+
+    let page = |viewer: Option<&ExpandedUser>| {
+        let (context, alice) = (context.clone(), alice.clone());
+        let viewer = viewer.cloned();
+        async move {
+            let (_, viewer, workspace) = get_public(&context, viewer.as_ref(), &alice, broken.id)
+                .await
+                .unwrap();
+            let content = workspace
+                .workspace
+                .render_content(
+                    &context,
+                    &workspace.user,
+                    &workspace.tree,
+                    models::workspace::Audience::Public,
+                )
+                .await;
+            assert!(!content.diagnostics.is_empty());
+            crate::views::feed::workspace_page(viewer, workspace, content)
+        }
+    };
+
+    // visitors are only told it didn't work
+    let html = page(None).await;
+    assert!(!html.contains("nope"));
+    assert!(html.contains("could not be shown"));
+    assert!(!page(Some(&bob)).await.contains("nope"));
+    let invite_filter = routes::workspace::invite_member();
+    let invite = |role: &str| {
+        form_post(
+            format!("/workspace/{}/members", alice_root.id),
+            &alice,
+            &format!("username={}&role={}", bob.user.username, role),
+        )
+        .extension(context.clone())
+        .filter(&invite_filter)
+    };
+    invite("viewer").await.unwrap();
+    assert!(!page(Some(&bob)).await.contains("nope"));
+
+    // those who could fix it see why
+    invite("editor").await.unwrap();
+    assert!(page(Some(&bob)).await.contains("nope"));
+    assert!(page(Some(&alice)).await.contains("nope"));
+}
@@ -1,13 +1,20 @@
 use crate::{
     models::{self, user::ExpandedUser},
-    routes,
-    utils::now,
-    Context, ExpandedUserRejection, NotAuthorized, NotFound, OldCookie, ResourceError, ServerError,
+    routes, sandbox,
+    utils::{random_token, sanitize_css},
+    Context, ExpandedUserRejection, InvalidSignup, NotAuthorized, NotFound, OldCookie, ResourceError,
+    ServerError,
     GLOBAL_PRELUDE,
 };
-use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+use diesel::{
+    result::{DatabaseErrorKind, Error::DatabaseError},
+    Connection, OptionalExtension,
+};
+use serde::de::DeserializeOwned;
+use subtle::ConstantTimeEq;
 use warp::{
     filters::{self, BoxedFilter},
+    hyper::body::Bytes,
     reject, Filter,
 };
 
@@ -33,6 +40,9 @@ pub fn signup() -> BoxedFilter<(
         .and(warp::body::form::<models::user::NewUserApi>())
         .and_then(insert_new_user)
         .untuple_one()
+        .map(|context, user| (context, user, false))
+        .untuple_one()
+        .and(warp::header::optional::<String>("user-agent"))
         .and_then(with_new_session)
         .untuple_one()
         .and_then(routes::workspace::insert_root_workspace)
@@ -52,6 +62,7 @@ pub fn login() -> BoxedFilter<(
         .and(warp::body::form::<models::user::UserCredentialsApi>())
         .and_then(with_user_by_credentials)
         .untuple_one()
+        .and(warp::header::optional::<String>("user-agent"))
         .and_then(with_new_session)
         .untuple_one()
         .and_then(routes::workspace::with_root_workspace)
@@ -59,6 +70,101 @@ pub fn login() -> BoxedFilter<(
         .boxed()
 }
 
+pub fn sessions() -> BoxedFilter<(Context, models::user::ExpandedUser, Vec<models::session::Session>)> {
+    warp::path("sessions")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(authenticate_cookie())
+        .and_then(with_sessions)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn revoke_session() -> BoxedFilter<(Context, models::user::ExpandedUser, Vec<models::session::Session>)> {
+    warp::path("sessions")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revoke"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticate_cookie())
+        .and(check_csrf())
+        .and_then(with_revoked_session)
+        .untuple_one()
+        .and_then(with_sessions)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn revoke_other_sessions() -> BoxedFilter<(Context, models::user::ExpandedUser, Vec<models::session::Session>)> {
+    warp::path("sessions")
+        .and(warp::path("revoke-others"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticate_cookie())
+        .and(check_csrf())
+        .and_then(with_other_sessions_revoked)
+        .untuple_one()
+        .and_then(with_sessions)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn settings() -> BoxedFilter<(Context, models::user::ExpandedUser, Option<String>)> {
+    warp::path("settings")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(authenticate_cookie())
+        .map(|context, expanded_user| (context, expanded_user, None))
+        .untuple_one()
+        .boxed()
+}
+
+pub fn change_password() -> BoxedFilter<(Context, models::user::ExpandedUser, Option<String>)> {
+    warp::path("settings")
+        .and(warp::path("password"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticate_cookie())
+        .and(form::<models::user::ChangePasswordApi>())
+        .and_then(with_changed_password)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn change_username() -> BoxedFilter<(Context, models::user::ExpandedUser, Option<String>)> {
+    warp::path("settings")
+        .and(warp::path("username"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticate_cookie())
+        .and(form::<models::user::ChangeUsernameApi>())
+        .and_then(with_changed_username)
+        .untuple_one()
+        .boxed()
+}
+
+// asks once more before the account goes
+pub fn delete_account_page() -> BoxedFilter<(Context, models::user::ExpandedUser)> {
+    warp::path("settings")
+        .and(warp::path("delete"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(authenticate_cookie())
+        .boxed()
+}
+
+pub fn delete_account() -> BoxedFilter<()> {
+    warp::path("settings")
+        .and(warp::path("delete"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticate_cookie())
+        .and(form::<models::user::DeleteAccountApi>())
+        .and_then(with_deleted_account)
+        .untuple_one()
+        .boxed()
+}
+
 pub fn get_by_cookie() -> BoxedFilter<(
     Context,
     models::user::ExpandedUser,
@@ -75,12 +181,13 @@ pub fn get_by_cookie() -> BoxedFilter<(
 async fn with_user_by_credentials(
     context: Context,
     credentials: models::user::UserCredentialsApi,
-) -> Result<(Context, models::user::User), warp::Rejection> {
+) -> Result<(Context, models::user::User, bool), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
     tracing::info!("Looking for user {}", credentials.username);
+    let remember = credentials.remember;
     let user = models::user::User::read_by_credentials(&mut conn, credentials)
         .map_err(|_| reject::custom(NotFound))?;
-    Ok((context, user))
+    Ok((context, user, remember))
 }
 
 async fn insert_new_user(
@@ -89,17 +196,35 @@ async fn insert_new_user(
 ) -> Result<(Context, models::user::User), warp::Rejection> {
     tracing::debug!("Saving User");
     let mut conn = context.db_conn.get_conn();
+    let username = new_user.username.clone();
+    let taken = || {
+        reject::custom(InvalidSignup {
+            username: username.clone(),
+            errors: models::user::SignupErrors {
+                username: Some(String::from("This username is taken.")),
+                ..Default::default()
+            },
+        })
+    };
+
+    let errors = new_user.validate();
+    if !errors.is_empty() {
+        return Err(reject::custom(InvalidSignup {
+            username: username.clone(),
+            errors,
+        }));
+    }
+    // names people have moved on from keep leading to them
+    if models::username_redirect::UsernameRedirect::read_by_username(&mut conn, &username).is_ok() {
+        return Err(taken());
+    }
 
     let user = models::user::NewUser::new(new_user.into())
         .insert(&mut conn)
         .map_err(|e| {
             tracing::error!("{:?}", e);
             match e {
-                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                    reject::custom(ResourceError {
-                        message: String::from("This user already exists."),
-                    })
-                }
+                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => taken(),
                 err => {
                     tracing::error!("{:?}", err);
                     reject::custom(ServerError {
@@ -126,45 +251,250 @@ pub fn login_form() -> BoxedFilter<()> {
         .boxed()
 }
 
+// one more session, the user's others on other devices carry on
 async fn with_new_session(
     context: Context,
     user: models::user::User,
+    remember: bool,
+    user_agent: Option<String>,
 ) -> Result<(Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    models::session::delete_by_user_id(&mut conn, user.id).map_err(|err| {
-        tracing::error!("{:?}", err);
-        warp::reject()
-    })?;
-
-    let session = models::session::NewSession::new(user.id)
+    let token = random_token();
+    let session = models::session::NewSession::new(&context.config, user.id, &token, user_agent, remember)
         .insert(&mut conn)
-        .map_err(|_| {
-            warp::reject::custom(ResourceError {
-                message: String::from("You cannot log in on more than one device."),
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
             })
         })?;
 
-    let expanded_user = ExpandedUser { user, session };
+    let expanded_user = ExpandedUser {
+        user,
+        session,
+        token,
+        refreshed_cookie: None,
+    };
     Ok((context, expanded_user))
 }
 
+async fn with_sessions(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(Context, models::user::ExpandedUser, Vec<models::session::Session>), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let sessions = models::session::read_active_by_user_id(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((context, expanded_user, sessions))
+}
+
+async fn with_revoked_session(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    if id == expanded_user.session.id {
+        return Err(reject::custom(ResourceError {
+            message: String::from("Log out to end the session you are using."),
+        }));
+    }
+    // someone else's session is as good as no session
+    let session = models::session::read_active_by_user_id_and_id(&mut conn, expanded_user.user.id, id)
+        .map_err(|_| reject::custom(NotFound))?;
+
+    models::session::delete(&mut conn, &session).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((context, expanded_user))
+}
+
+async fn with_other_sessions_revoked(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    models::session::delete_others(&mut conn, &expanded_user.session).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((context, expanded_user))
+}
+
+// the other sessions end along with the old password
+async fn with_changed_password(
+    context: Context,
+    mut expanded_user: models::user::ExpandedUser,
+    change: models::user::ChangePasswordApi,
+) -> Result<(Context, models::user::ExpandedUser, Option<String>), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    if !expanded_user.user.verify_password(&change.current_password) {
+        return Ok((
+            context,
+            expanded_user,
+            Some(String::from("Your current password is not right.")),
+        ));
+    }
+    if let Err(message) =
+        models::user::validate_password(&expanded_user.user.username, &change.new_password)
+    {
+        return Ok((context, expanded_user, Some(message)));
+    }
+    if change.new_password != change.confirm_password {
+        return Ok((
+            context,
+            expanded_user,
+            Some(String::from("The new passwords do not match.")),
+        ));
+    }
+
+    // signed out elsewhere along with the change, or not changed at all
+    conn.transaction(|conn| {
+        expanded_user.user.update_password(conn, &change.new_password)?;
+        models::session::delete_others(conn, &expanded_user.session)
+    })
+    .map_err(|e: diesel::result::Error| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((
+        context,
+        expanded_user,
+        Some(String::from(
+            "Password changed. Every other session has been logged out.",
+        )),
+    ))
+}
+
+async fn with_changed_username(
+    context: Context,
+    mut expanded_user: models::user::ExpandedUser,
+    change: models::user::ChangeUsernameApi,
+) -> Result<(Context, models::user::ExpandedUser, Option<String>), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+    let username = change.username;
+    let taken = Some(String::from("This username is taken."));
+
+    if let Err(message) = models::user::validate_username(&username) {
+        return Ok((context, expanded_user, Some(message)));
+    }
+    if username == expanded_user.user.username {
+        return Ok((
+            context,
+            expanded_user,
+            Some(String::from("That is already your username.")),
+        ));
+    }
+    // someone else's old name still leads to them
+    match models::username_redirect::UsernameRedirect::read_by_username(&mut conn, &username) {
+        Ok(redirect) if redirect.user_id != expanded_user.user.id => {
+            return Ok((context, expanded_user, taken))
+        }
+        Ok(_) | Err(diesel::result::Error::NotFound) => (),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            return Err(reject::custom(ServerError {
+                message: e.to_string(),
+            }));
+        }
+    }
+
+    match expanded_user.user.rename(&mut conn, username) {
+        Ok(_) => (),
+        Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            return Ok((context, expanded_user, taken))
+        }
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            return Err(reject::custom(ServerError {
+                message: e.to_string(),
+            }));
+        }
+    }
+    // rendered pages link to the author by name
+    models::workspace::Workspace::clear_rendered_by_user(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((
+        context,
+        expanded_user,
+        Some(String::from(
+            "Username changed. Links with your old one will keep working.",
+        )),
+    ))
+}
+
+async fn with_deleted_account(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    confirmation: models::user::DeleteAccountApi,
+) -> Result<(), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    if !expanded_user.user.verify_password(&confirmation.password) {
+        return Err(reject::custom(ResourceError {
+            message: String::from("Your password is not right, your account has not been deleted."),
+        }));
+    }
+
+    expanded_user.user.delete(&mut conn).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+    tracing::info!("Deleted user {}", expanded_user.user.id);
+
+    Ok(())
+}
+
 async fn clear_session(
     context: Context,
-    session: models::session::Session,
+    session: Option<models::session::Session>,
 ) -> Result<(), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
-    models::session::delete(&mut conn, &session).map_err(|_| warp::reject::custom(NotFound))?;
+    if let Some(session) = session {
+        models::session::delete(&mut conn, &session).map_err(|_| warp::reject::custom(NotFound))?;
+    }
     Ok(())
 }
 
 async fn with_user_from_cookie(
     context: Context,
-    session_id: i32,
+    token: String,
 ) -> Result<(Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
-    tracing::info!("Session ID: {}", session_id);
-    let expanded_user = models::user::read_user_by_session(&mut conn, session_id)
+    // a cookie from before tokens holds a bare session id
+    if !models::session::is_well_formed(&token) {
+        return Err(warp::reject::custom(OldCookie));
+    }
+    let mut expanded_user = models::user::read_user_by_session_token(&mut conn, &token)
         .map_err(|_| warp::reject::custom(NotAuthorized))?;
     tracing::info!(
         "Recognized user {:?} from {:?}",
@@ -172,23 +502,37 @@ async fn with_user_from_cookie(
         expanded_user.session
     );
 
-    if expanded_user.session.valid_until < now() {
+    if expanded_user.session.is_expired() {
         models::session::delete(&mut conn, &expanded_user.session)
             .map_err(|_| warp::reject::custom(NotFound))?;
         return Err(warp::reject::custom(OldCookie));
     }
 
+    // a failed write only costs the sessions page some accuracy. A cookie
+    // that outlives the browser has to move along with the session, or it
+    // would go a lifetime after login however much it's used
+    match models::session::touch(&mut conn, &context.config, &mut expanded_user.session) {
+        Ok(true) if expanded_user.session.is_remembered => {
+            expanded_user.refreshed_cookie = Some(models::session::cookie(
+                &token,
+                Some(models::session::lifetime(&context.config, true)),
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!("{:?}", e),
+    }
+
     Ok((context, expanded_user))
 }
 
 async fn reject_with_user(
     context: Context,
-    session_id: i32,
+    token: String,
 ) -> Result<(Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
     tracing::error!("Adding user object into this rejection");
     let expanded_user =
-        models::user::read_user_by_session(&mut conn, session_id).map_err(|_| {
+        models::user::read_user_by_session_token(&mut conn, &token).map_err(|_| {
             warp::reject::custom(ExpandedUserRejection {
                 expanded_user: None,
             })
@@ -210,12 +554,21 @@ async fn reject_without_user(_context: Context) -> Result<(), warp::Rejection> {
 
 async fn with_session_from_cookie(
     context: Context,
-    session_id: i32,
-) -> Result<(Context, models::session::Session), warp::Rejection> {
+    token: Option<String>,
+) -> Result<(Context, Option<models::session::Session>), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    let session = models::session::read_by_id(&mut conn, session_id)
-        .map_err(|_| warp::reject::custom(NotFound))?;
+    let session = match token {
+        Some(token) => models::session::read_by_token(&mut conn, &token)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                reject::custom(ServerError {
+                    message: e.to_string(),
+                })
+            })?,
+        None => None,
+    };
 
     Ok((context, session))
 }
@@ -229,6 +582,52 @@ pub fn authenticate_cookie() -> BoxedFilter<(Context, models::user::ExpandedUser
         .boxed()
 }
 
+/// The fields of a form post, once the csrf token among them is found to
+/// belong to the session it came with, so a form on another site can't
+/// post on a user's behalf. Every route that changes something takes its
+/// form through here rather than `warp::body::form`.
+pub fn form<T: DeserializeOwned + Send + 'static>() -> BoxedFilter<(T,)> {
+    warp::any()
+        .and(warp::cookie::optional::<String>("session"))
+        .and(warp::body::bytes())
+        .and_then(with_checked_form::<T>)
+        .boxed()
+}
+
+// for form posts that carry nothing but the csrf token
+pub fn check_csrf() -> BoxedFilter<()> {
+    form::<models::session::CsrfApi>()
+        .map(|_| ())
+        .untuple_one()
+        .boxed()
+}
+
+async fn with_checked_form<T: DeserializeOwned>(
+    token: Option<String>,
+    body: Bytes,
+) -> Result<T, warp::Rejection> {
+    let posted = serde_urlencoded::from_bytes::<models::session::CsrfApi>(&body)
+        .map_err(|_| reject::custom(NotAuthorized))?;
+    // compared in constant time, so how long it takes says nothing of the token
+    let matches = |token: &str| -> bool {
+        let expected = models::session::csrf_token(token);
+        posted.csrf_token.as_bytes().ct_eq(expected.as_bytes()).into()
+    };
+    match token {
+        Some(token) if matches(&token) => (),
+        _ => {
+            tracing::error!("Form posted without the session's csrf token");
+            return Err(reject::custom(NotAuthorized));
+        }
+    }
+
+    serde_urlencoded::from_bytes::<T>(&body).map_err(|e| {
+        reject::custom(ResourceError {
+            message: e.to_string(),
+        })
+    })
+}
+
 pub fn logged_in_rejection() -> BoxedFilter<(
     Context,
     models::user::ExpandedUser,
@@ -252,22 +651,56 @@ pub fn logged_out_rejection() -> BoxedFilter<()> {
         .boxed()
 }
 
-pub fn read_cookie() -> BoxedFilter<(Context, models::session::Session)> {
+pub fn read_cookie() -> BoxedFilter<(Context, Option<models::session::Session>)> {
     warp::any()
         .and(filters::ext::get::<Context>())
-        .and(warp::cookie("session"))
+        .and(warp::cookie::optional("session"))
         .and_then(with_session_from_cookie)
         .untuple_one()
         .boxed()
 }
 
+// a user's saved stylesheet, along with the etag the browser already has
+pub fn stylesheet() -> BoxedFilter<(Context, String, Option<String>)> {
+    warp::path::param::<String>()
+        .and(warp::path("style.css"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(filters::ext::get::<Context>())
+        .and_then(with_user_by_username)
+        .untuple_one()
+        .map(|context, user: models::user::User| (context, user.stylesheet()))
+        .untuple_one()
+        .and(warp::header::optional::<String>("if-none-match"))
+        .boxed()
+}
+
+async fn with_user_by_username(
+    username: String,
+    context: Context,
+) -> Result<(Context, models::user::User), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let user = models::user::User::read_by_username(&mut conn, username).map_err(|e| match e {
+        diesel::result::Error::NotFound => reject::custom(NotFound),
+        e => {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        }
+    })?;
+
+    Ok((context, user))
+}
+
 pub fn update_style() -> BoxedFilter<(Context, models::user::ExpandedUser, Option<String>)> {
     warp::path("stylesheet")
         .and(warp::path::end())
         .and(
             warp::post()
                 .and(routes::user::authenticate_cookie())
-                .and(warp::body::form::<models::user::UpdateStyleApi>())
+                .and(routes::user::form::<models::user::UpdateStyleApi>())
                 .and_then(update_user_style)
                 .untuple_one()
                 .or(warp::get()
@@ -285,7 +718,7 @@ pub fn update_prelude() -> BoxedFilter<(Context, models::user::ExpandedUser, Opt
         .and(
             warp::post()
                 .and(routes::user::authenticate_cookie())
-                .and(warp::body::form::<models::user::UpdatePreludeApi>())
+                .and(routes::user::form::<models::user::UpdatePreludeApi>())
                 .and_then(update_user_prelude)
                 .untuple_one()
                 .or(warp::get()
@@ -304,7 +737,7 @@ async fn update_user_prelude(
 ) -> Result<(Context, models::user::ExpandedUser, Option<String>), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    let input = &format!(
+    let input = format!(
         r#"
             {}
             {}
@@ -312,8 +745,14 @@ async fn update_user_prelude(
         GLOBAL_PRELUDE,
         new_prelude.prelude.clone()
     );
-    let mut env = bebop_lang::lisp::env::init_env();
-    let v = bebop_lang::lisp::lisp(&mut env, input);
+    let v = context
+        .sandbox
+        .run(move || {
+            let mut env = sandbox::init_env();
+            sandbox::lisp(&mut env, &input)
+        })
+        .await
+        .unwrap_or_else(|e| e.to_string());
 
     expanded_user.user.prelude = Some(new_prelude.prelude);
     expanded_user.user.update(&mut conn).map_err(|e| {
@@ -322,6 +761,13 @@ async fn update_user_prelude(
             message: e.to_string(),
         })
     })?;
+    models::workspace::Workspace::clear_rendered_by_user(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
 
     Ok((context, expanded_user, Some(v)))
 }
@@ -333,7 +779,7 @@ async fn update_user_style(
 ) -> Result<(Context, models::user::ExpandedUser, Option<String>), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    expanded_user.user.style = Some(new_style.style);
+    expanded_user.user.style = Some(sanitize_css(&new_style.style));
     expanded_user.user.update(&mut conn).map_err(|e| {
         tracing::error!("{:?}", e);
         reject::custom(ServerError {
@@ -343,3 +789,516 @@ async fn update_user_style(
 
     Ok((context, expanded_user, Some(String::from("Style updated!"))))
 }
+
+#[tokio::test]
+async fn test_stylesheet_is_sanitized_and_cached() {
+    let context = routes::workspace::test_context();
+    let (alice, _) = routes::workspace::signed_in_user(&context, "alice");
+
+    let (_, alice, _) = routes::workspace::form_post(
+        String::from("/stylesheet"),
+        &alice,
+        "style=h1+%7B+color%3A+red%3B+background%3A+url(https%3A%2F%2Fevil.example)%3B+%7D",
+    )
+    .extension(context.clone())
+    .filter(&update_style())
+    .await
+    .unwrap();
+    assert_eq!(
+        alice.user.style,
+        Some(String::from("h1 { color: red; background: none; }"))
+    );
+
+    let filter = stylesheet().and_then(crate::handlers::user::stylesheet);
+    let path = format!("/{}/style.css", alice.user.username);
+    let res = warp::test::request()
+        .path(&path)
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/css; charset=utf-8");
+    assert_eq!(res.body(), "h1 { color: red; background: none; }");
+
+    let res = warp::test::request()
+        .path(&path)
+        .header("if-none-match", alice.user.stylesheet_etag())
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(res.status(), 304);
+}
+
+#[tokio::test]
+async fn test_multiple_sessions() {
+    let context = routes::workspace::test_context();
+    let (alice, _) = routes::workspace::signed_in_user(&context, "alice");
+    let (bob, _) = routes::workspace::signed_in_user(&context, "bob");
+
+    // logging in on the phone leaves the laptop logged in
+    let (_, phone, _) = warp::test::request()
+        .method("POST")
+        .path("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("user-agent", "Phone <Browser>")
+        .body(format!("username={}&password=password", alice.user.username))
+        .extension(context.clone())
+        .filter(&login())
+        .await
+        .unwrap();
+    assert_ne!(phone.session.id, alice.session.id);
+
+    let list = sessions();
+    let (_, _, listed) = warp::test::request()
+        .path("/sessions")
+        .header("cookie", routes::workspace::session_cookie(&alice))
+        .extension(context.clone())
+        .filter(&list)
+        .await
+        .unwrap();
+    let ids: Vec<i32> = listed.iter().map(|session| session.id).collect();
+    assert!(ids.contains(&alice.session.id) && ids.contains(&phone.session.id));
+    assert_eq!(listed.len(), 2);
+    let html = crate::views::user::sessions_page(alice.clone(), listed);
+    assert!(html.contains("Phone &lt;Browser&gt;"));
+    assert!(html.contains(&format!("/sessions/{}/revoke", phone.session.id)));
+    assert!(!html.contains(&format!("/sessions/{}/revoke", alice.session.id)));
+
+    let revoke = revoke_session();
+    let rejection = routes::workspace::form_post(format!("/sessions/{}/revoke", alice.session.id), &alice, "")
+        .extension(context.clone())
+        .filter(&revoke)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    let rejection = routes::workspace::form_post(format!("/sessions/{}/revoke", phone.session.id), &bob, "")
+        .extension(context.clone())
+        .filter(&revoke)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<NotFound>().is_some());
+
+    let (_, _, listed) = routes::workspace::form_post(String::from("/sessions/revoke-others"), &phone, "")
+        .extension(context.clone())
+        .filter(&revoke_other_sessions())
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, phone.session.id);
+    let rejection = warp::test::request()
+        .path("/sessions")
+        .header("cookie", routes::workspace::session_cookie(&alice))
+        .extension(context.clone())
+        .filter(&list)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+}
+
+#[tokio::test]
+async fn test_sessions_slide_and_expire() {
+    use crate::{schema::session, utils::now};
+    use diesel::prelude::*;
+
+    let context = routes::workspace::test_context();
+    let (alice, _) = routes::workspace::signed_in_user(&context, "alice");
+    let mut conn = context.db_conn.get_conn();
+    let list = sessions();
+    let visit = |expanded_user: &ExpandedUser| {
+        warp::test::request()
+            .path("/sessions")
+            .header("cookie", routes::workspace::session_cookie(expanded_user))
+            .extension(context.clone())
+            .filter(&list)
+    };
+    let set = |valid_until: chrono::NaiveDateTime, last_seen_at: chrono::NaiveDateTime| {
+        let mut conn = context.db_conn.get_conn();
+        diesel::update(session::table)
+            .filter(session::id.eq(alice.session.id))
+            .set((
+                session::valid_until.eq(valid_until),
+                session::last_seen_at.eq(Some(last_seen_at)),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+    };
+    let idle = models::session::lifetime(&context.config, false);
+
+    // used a while ago, so this visit pushes the expiry out again
+    set(now() + chrono::Duration::minutes(1), now() - chrono::Duration::minutes(10));
+    visit(&alice).await.unwrap();
+    let slid = models::session::read_by_id(&mut conn, alice.session.id).unwrap();
+    assert!(slid.valid_until > now() + idle - chrono::Duration::minutes(1));
+
+    // used a moment ago, so nothing is written
+    let soon = now() + chrono::Duration::minutes(1);
+    set(soon, now());
+    visit(&alice).await.unwrap();
+    let kept = models::session::read_by_id(&mut conn, alice.session.id).unwrap();
+    assert!(kept.valid_until <= soon);
+
+    // remembered sessions last far longer than idle ones
+    let (_, remembered, _) = warp::test::request()
+        .method("POST")
+        .path("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("username={}&password=password&remember=true", alice.user.username))
+        .extension(context.clone())
+        .filter(&login())
+        .await
+        .unwrap();
+    assert!(remembered.session.is_remembered);
+    assert!(remembered.session.valid_until > now() + chrono::Duration::days(1));
+
+    // and once one runs out it's refused, then swept away
+    set(now() - chrono::Duration::minutes(1), now() - chrono::Duration::minutes(10));
+    let rejection = visit(&alice).await.err().unwrap();
+    assert!(rejection.find::<OldCookie>().is_some());
+    models::session::sweep(&mut conn).unwrap();
+    assert!(models::session::read_by_id(&mut conn, alice.session.id).is_err());
+    assert!(models::session::read_by_id(&mut conn, remembered.session.id).is_ok());
+}
+
+#[tokio::test]
+async fn test_session_cookies() {
+    let context = routes::workspace::test_context();
+    let (alice, _) = routes::workspace::signed_in_user(&context, "alice");
+    let mut conn = context.db_conn.get_conn();
+
+    // the cookie holds a random token, only its hash is kept
+    let filter = login().and_then(crate::handlers::user::profile_with_cookie);
+    let res = warp::test::request()
+        .method("POST")
+        .path("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("username={}&password=password", alice.user.username))
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    let cookie = res.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.ends_with("; Path=/; HttpOnly; Secure; SameSite=Lax"));
+    let token = cookie
+        .trim_start_matches("session=")
+        .split(';')
+        .next()
+        .unwrap();
+    assert!(models::session::is_well_formed(token));
+    let session = models::session::read_by_token(&mut conn, token).unwrap();
+    assert_ne!(session.token_hash, token);
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("username={}&password=password&remember=true", alice.user.username))
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    assert!(res.headers()["set-cookie"].to_str().unwrap().contains("; Max-Age="));
+
+    // a cookie from before tokens is turned away and cleared
+    let list = sessions();
+    let rejection = warp::test::request()
+        .path("/sessions")
+        .header("cookie", format!("session={}", alice.session.id))
+        .extension(context.clone())
+        .filter(&list)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<OldCookie>().is_some());
+    let res = warp::test::request()
+        .path("/sessions")
+        .header("cookie", format!("session={}", alice.session.id))
+        .extension(context.clone())
+        .reply(&list.clone().and_then(crate::handlers::user::sessions).recover(crate::handle_rejections))
+        .await;
+    assert_eq!(res.status(), 401);
+    assert_eq!(res.headers()["set-cookie"], models::session::CLEAR_COOKIE);
+
+    // logging out ends the session and clears the cookie, even a stale one
+    let filter = logout().and_then(crate::handlers::user::logout);
+    let res = warp::test::request()
+        .path("/logout")
+        .header("cookie", format!("session={}", token))
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(res.headers()["set-cookie"], models::session::CLEAR_COOKIE);
+    assert!(models::session::read_by_token(&mut conn, token).is_err());
+    let res = warp::test::request()
+        .path("/logout")
+        .header("cookie", "session=1")
+        .extension(context.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["set-cookie"], models::session::CLEAR_COOKIE);
+}
+
+#[tokio::test]
+async fn test_remembered_cookies_are_refreshed() {
+    use crate::{schema::session, utils::now};
+    use diesel::prelude::*;
+
+    let context = routes::workspace::test_context();
+    let (alice, _) = routes::workspace::signed_in_user(&context, "alice");
+    let filter = sessions().and_then(crate::handlers::user::sessions);
+    let visit = |expanded_user: &ExpandedUser| {
+        warp::test::request()
+            .path("/sessions")
+            .header("cookie", routes::workspace::session_cookie(expanded_user))
+            .extension(context.clone())
+            .reply(&filter)
+    };
+    let used_a_while_ago = |expanded_user: &ExpandedUser| {
+        let mut conn = context.db_conn.get_conn();
+        diesel::update(session::table)
+            .filter(session::id.eq(expanded_user.session.id))
+            .set(session::last_seen_at.eq(Some(now() - chrono::Duration::minutes(10))))
+            .execute(&mut conn)
+            .unwrap();
+    };
+    let (_, remembered, _) = warp::test::request()
+        .method("POST")
+        .path("/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("username={}&password=password&remember=true", alice.user.username))
+        .extension(context.clone())
+        .filter(&login())
+        .await
+        .unwrap();
+
+    // sliding the expiry sends the cookie again with a whole new lifetime
+    used_a_while_ago(&remembered);
+    let res = visit(&remembered).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.headers()["set-cookie"].to_str().unwrap(),
+        models::session::cookie(
+            &remembered.token,
+            Some(models::session::lifetime(&context.config, true))
+        )
+    );
+
+    // not when nothing moved, nor for cookies that end with the browser
+    let res = visit(&remembered).await;
+    assert!(res.headers().get("set-cookie").is_none());
+    used_a_while_ago(&alice);
+    let res = visit(&alice).await;
+    assert_eq!(res.status(), 200);
+    assert!(res.headers().get("set-cookie").is_none());
+}
+
+#[tokio::test]
+async fn test_account_settings() {
+    let context = routes::workspace::test_context();
+    let (alice, alice_root) = routes::workspace::signed_in_user(&context, "alice");
+    let (bob, _) = routes::workspace::signed_in_user(&context, "bob");
+    let mut conn = context.db_conn.get_conn();
+    let login_filter = login();
+    let log_in = |username: String, password: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/login")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(format!("username={}&password={}", username, password))
+            .extension(context.clone())
+            .filter(&login_filter)
+    };
+
+    // the password only changes with the current one, and the other
+    // sessions end with it
+    let (_, phone, _) = log_in(alice.user.username.clone(), "password").await.unwrap();
+    let password_filter = change_password();
+    let change = |body: &str| {
+        routes::workspace::form_post(String::from("/settings/password"), &alice, body)
+            .extension(context.clone())
+            .filter(&password_filter)
+    };
+    let (_, _, message) = change("current_password=wrong&new_password=newpassw0rd&confirm_password=newpassw0rd")
+        .await
+        .unwrap();
+    assert_eq!(message, Some(String::from("Your current password is not right.")));
+    let (_, _, message) = change("current_password=password&new_password=short&confirm_password=short")
+        .await
+        .unwrap();
+    assert_eq!(message, Some(String::from("Use at least 8 characters.")));
+    let (_, _, message) = change("current_password=password&new_password=newpassw0rd&confirm_password=oldpassw0rd")
+        .await
+        .unwrap();
+    assert_eq!(message, Some(String::from("The new passwords do not match.")));
+    change("current_password=password&new_password=newpassw0rd&confirm_password=newpassw0rd")
+        .await
+        .unwrap();
+    assert!(log_in(alice.user.username.clone(), "password").await.is_err());
+    log_in(alice.user.username.clone(), "newpassw0rd").await.unwrap();
+    assert!(models::session::read_by_token(&mut conn, &phone.token).is_err());
+    assert!(models::session::read_by_token(&mut conn, &alice.token).is_ok());
+
+    // a new username leaves the old one pointing at it
+    let old_username = alice.user.username.clone();
+    let new_username = format!("renamed{}", alice.user.id);
+    let username_filter = change_username();
+    let (_, renamed, _) = routes::workspace::form_post(
+        String::from("/settings/username"),
+        &alice,
+        &format!("username={}", new_username),
+    )
+    .extension(context.clone())
+    .filter(&username_filter)
+    .await
+    .unwrap();
+    assert_eq!(renamed.user.username, new_username);
+    let redirect = routes::feed::renamed();
+    let uri = warp::test::request()
+        .path(&format!("/{}/workspace/{}", old_username, alice_root.id))
+        .extension(context.clone())
+        .filter(&redirect)
+        .await
+        .unwrap();
+    assert_eq!(uri.to_string(), format!("/{}/workspace/{}", new_username, alice_root.id));
+    assert!(warp::test::request()
+        .path(&format!("/{}/rss", bob.user.username))
+        .extension(context.clone())
+        .filter(&redirect)
+        .await
+        .is_err());
+
+    // nobody else gets the old name, but alice can go back to it
+    let (_, _, message) = routes::workspace::form_post(
+        String::from("/settings/username"),
+        &bob,
+        &format!("username={}", old_username),
+    )
+    .extension(context.clone())
+    .filter(&username_filter)
+    .await
+    .unwrap();
+    assert_eq!(message, Some(String::from("This username is taken.")));
+    let rejection = warp::test::request()
+        .method("POST")
+        .path("/signup")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("username={}&password=passw0rd&confirm_password=passw0rd", old_username))
+        .extension(context.clone())
+        .filter(&signup())
+        .await
+        .err()
+        .unwrap();
+    let invalid = rejection.find::<InvalidSignup>().unwrap();
+    assert_eq!(invalid.errors.username, Some(String::from("This username is taken.")));
+    let (_, renamed, _) = routes::workspace::form_post(
+        String::from("/settings/username"),
+        &alice,
+        &format!("username={}", old_username),
+    )
+    .extension(context.clone())
+    .filter(&username_filter)
+    .await
+    .unwrap();
+    assert_eq!(renamed.user.username, old_username);
+    assert!(models::username_redirect::UsernameRedirect::read_by_username(&mut conn, &old_username).is_err());
+
+    // deleting takes the password, then the workspaces and sessions go too
+    let delete_filter = delete_account();
+    let rejection = routes::workspace::form_post(String::from("/settings/delete"), &alice, "password=password")
+        .extension(context.clone())
+        .filter(&delete_filter)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    routes::workspace::form_post(String::from("/settings/delete"), &alice, "password=newpassw0rd")
+        .extension(context.clone())
+        .filter(&delete_filter)
+        .await
+        .unwrap();
+    assert!(models::user::User::read_by_id(&mut conn, alice.user.id).is_err());
+    assert!(models::workspace::Workspace::read_by_id(&mut conn, alice_root.id).is_err());
+    assert!(models::session::read_active_by_user_id(&mut conn, alice.user.id)
+        .unwrap()
+        .is_empty());
+    assert!(log_in(alice.user.username.clone(), "newpassw0rd").await.is_err());
+}
+
+#[tokio::test]
+async fn test_signup_validation() {
+    let context = routes::workspace::test_context();
+    let filter = signup().and_then(crate::handlers::user::profile_with_cookie);
+    let sign_up = |body: String| {
+        warp::test::request()
+            .method("POST")
+            .path("/signup")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+            .extension(context.clone())
+            .filter(&filter)
+    };
+    let errors_for = |username: &str, password: &str, confirm_password: &str| {
+        models::user::NewUserApi {
+            username: username.to_string(),
+            password: password.to_string(),
+            confirm_password: confirm_password.to_string(),
+        }
+        .validate()
+    };
+
+    let errors = errors_for("rss", "passw0rd", "passw0rd");
+    assert_eq!(errors.username, Some(String::from("This username is reserved.")));
+    assert!(errors.password.is_none() && errors.confirm_password.is_none());
+    assert!(errors_for("Workspace", "passw0rd", "passw0rd").username.is_some());
+    assert!(errors_for("bad name", "passw0rd", "passw0rd").username.is_some());
+    assert!(errors_for("<b>x</b>", "passw0rd", "passw0rd").username.is_some());
+    assert!(errors_for(&"a".repeat(49), "passw0rd", "passw0rd").username.is_some());
+    assert!(errors_for(&"a".repeat(48), "passw0rd", "passw0rd").is_empty());
+    assert!(errors_for("alice", "short1", "short1").password.is_some());
+    assert!(errors_for("alice", "password", "password").password.is_some());
+    assert!(errors_for("alice", "12345678", "12345678").password.is_some());
+    assert!(errors_for("alice12345", "alice12345", "alice12345").password.is_some());
+    let errors = errors_for("alice", "passw0rd", "passw0rdd");
+    assert_eq!(errors.confirm_password, Some(String::from("The passwords do not match.")));
+    assert!(errors.username.is_none() && errors.password.is_none());
+
+    // the form comes back with each problem under its field
+    let rejection = sign_up(String::from(
+        "username=%22%3Eworkspace&password=password&confirm_password=other",
+    ))
+    .await
+    .err()
+    .unwrap();
+    let invalid = rejection.find::<InvalidSignup>().unwrap();
+    assert!(invalid.errors.username.is_some());
+    assert!(invalid.errors.password.is_some());
+    assert!(invalid.errors.confirm_password.is_some());
+    let res = warp::test::request()
+        .method("POST")
+        .path("/signup")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("username=%22%3Eworkspace&password=password&confirm_password=other")
+        .extension(context.clone())
+        .reply(&filter.clone().recover(crate::handlers::user::signup_error))
+        .await;
+    assert_eq!(res.status(), 400);
+    let html = String::from_utf8(res.body().to_vec()).unwrap();
+    assert!(html.contains(r#"value="&quot;&gt;workspace""#));
+    assert!(html.contains("Use only letters and numbers."));
+    assert!(html.contains("Mix letters with numbers or symbols."));
+    assert!(html.contains("The passwords do not match."));
+
+    let username = format!("carol{}", crate::utils::now().and_utc().timestamp_nanos_opt().unwrap());
+    sign_up(format!("username={}&password=passw0rd&confirm_password=passw0rd", username))
+        .await
+        .unwrap();
+    let rejection = sign_up(format!("username={}&password=passw0rd&confirm_password=passw0rd", username))
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        rejection.find::<InvalidSignup>().unwrap().errors.username,
+        Some(String::from("This username is taken."))
+    );
+}
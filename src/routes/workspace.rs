@@ -1,4 +1,8 @@
-use crate::{models, routes, Context, ServerError};
+use crate::{
+    models::{self, workspace_member::Role},
+    routes, Context, NotAuthorized, NotFound, ResourceError, ServerError,
+};
+use diesel::PgConnection;
 use warp::{filters::BoxedFilter, reject, Filter};
 
 pub fn workspace() -> BoxedFilter<(
@@ -27,6 +31,8 @@ pub fn edit_page() -> BoxedFilter<(
         .and(warp::path::end())
         .and(warp::get())
         .and(routes::user::authenticate_cookie())
+        .and_then(with_editable_workspace)
+        .untuple_one()
         .and_then(with_workspace)
         .untuple_one()
         .boxed()
@@ -43,7 +49,9 @@ pub fn new() -> BoxedFilter<(
         .and(warp::path::end())
         .and(warp::post())
         .and(routes::user::authenticate_cookie())
-        .and(warp::body::form::<models::workspace::NewWorkspaceApi>())
+        .and_then(with_editable_workspace)
+        .untuple_one()
+        .and(routes::user::form::<models::workspace::NewWorkspaceApi>())
         .and_then(with_new_workspace)
         .untuple_one()
         .and_then(with_workspace)
@@ -61,7 +69,9 @@ pub fn edit() -> BoxedFilter<(
         .and(warp::path::end())
         .and(warp::post())
         .and(routes::user::authenticate_cookie())
-        .and(warp::body::form::<models::workspace::EditWorkspaceApi>())
+        .and_then(with_editable_workspace)
+        .untuple_one()
+        .and(routes::user::form::<models::workspace::EditWorkspaceApi>())
         .and_then(with_update_workspace)
         .untuple_one()
         .and_then(with_workspace)
@@ -80,7 +90,9 @@ pub fn publish() -> BoxedFilter<(
         .and(warp::path::end())
         .and(warp::post())
         .and(routes::user::authenticate_cookie())
-        .and(warp::body::form::<models::workspace::PublishWorkspaceApi>())
+        .and_then(with_editable_workspace)
+        .untuple_one()
+        .and(routes::user::form::<models::workspace::PublishWorkspaceApi>())
         .and_then(with_publish_workspace)
         .untuple_one()
         .and_then(with_workspace)
@@ -88,20 +100,746 @@ pub fn publish() -> BoxedFilter<(
         .boxed()
 }
 
-pub async fn with_root_workspace(
+pub fn history() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::workspace_revision::RevisionWithAuthor>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_revisions)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn share_page() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::share_link::ShareLink>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("share"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_administered_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_share_links)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn new_share_link() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::share_link::ShareLink>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("share"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::form::<models::share_link::NewShareLinkApi>())
+        .and_then(with_new_share_link)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_share_links)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn revoke_share_link() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::share_link::ShareLink>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("share"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revoke"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::check_csrf())
+        .and_then(with_revoke_share_link)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_share_links)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn members_page() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::workspace_member::MemberWithUser>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("members"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_administered_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_members)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn invite_member() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::workspace_member::MemberWithUser>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("members"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::form::<models::workspace_member::NewWorkspaceMemberApi>())
+        .and_then(with_invite_member)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_members)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn remove_member() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::workspace_member::MemberWithUser>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("members"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::check_csrf())
+        .and_then(with_remove_member)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_members)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn shared() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    Vec<models::workspace_member::SharedWorkspace>,
+)> {
+    warp::path("shared")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_shared)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn revision_diff() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    models::workspace_revision::WorkspaceRevision,
+    models::workspace_revision::WorkspaceRevision,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("history"))
+        .and(warp::path("diff"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_workspace)
+        .untuple_one()
+        .and(warp::query::<models::workspace_revision::DiffRevisionsApi>())
+        .and_then(with_revision_diff)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn restore() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("history"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::check_csrf())
+        .and_then(with_restore_revision)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn move_page() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+    Vec<models::workspace::Workspace>,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("move"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_administered_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .and_then(with_user_workspaces)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn move_workspace() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("move"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::form::<models::workspace::MoveWorkspaceApi>())
+        .and_then(with_move_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+// shows the parent, the workspace is gone from its children
+pub fn delete() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("delete"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::check_csrf())
+        .and_then(with_delete_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn trash() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    Vec<models::workspace::TrashedWorkspace>,
+)> {
+    warp::path("trash")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(routes::user::authenticate_cookie())
+        .and_then(with_trash)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn restore_from_trash() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("trash")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::check_csrf())
+        .and_then(with_restore_from_trash)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+// shows the parent, where the new order can be seen
+pub fn reorder() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("reorder"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::form::<models::workspace::ReorderWorkspaceApi>())
+        .and_then(with_reorder_workspace)
+        .untuple_one()
+        .and_then(with_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+pub fn preview() -> BoxedFilter<(
+    Context,
+    models::user::ExpandedUser,
+    models::workspace::WorkspaceWithChildren,
+)> {
+    warp::path("workspace")
+        .and(warp::path::param::<i32>())
+        .and(warp::path("preview"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(routes::user::authenticate_cookie())
+        .and(routes::user::form::<models::workspace::EditWorkspaceApi>())
+        .and_then(with_preview_workspace)
+        .untuple_one()
+        .boxed()
+}
+
+pub async fn with_root_workspace(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = models::workspace::WorkspaceWithChildren::read_root_by_user(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((context, expanded_user, workspace.unwrap()))
+}
+
+async fn with_update_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    edit_workspace: models::workspace::EditWorkspaceApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    edit_workspace.update(&mut conn, id, expanded_user.user.id).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((id, context, expanded_user))
+}
+
+// the workspace as it would be with the posted edits, nothing is saved
+async fn with_preview_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    edit_workspace: models::workspace::EditWorkspaceApi,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    require_role(&mut conn, &expanded_user, id, Role::Editor)?;
+    // the tree too, for the preludes the draft inherits
+    let mut workspace = models::workspace::WorkspaceWithChildren::read_by_user_and_id(&mut conn, expanded_user.user.id, id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?
+        .ok_or(reject::custom(NotFound))?;
+    workspace.workspace = edit_workspace.preview(workspace.workspace);
+
+    Ok((context, expanded_user, workspace))
+}
+
+async fn with_publish_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    publish_workspace: models::workspace::PublishWorkspaceApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    publish_workspace.publish(&mut conn, id).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_user_workspaces(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+        Vec<models::workspace::Workspace>,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let memberships = models::workspace_member::WorkspaceMember::read_by_user(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+    // anywhere in the owner's tree the user could move it themselves
+    let workspaces = workspace
+        .tree
+        .iter()
+        .filter(|other| {
+            Role::of(expanded_user.user.id, other, &workspace.tree, &memberships)
+                .is_some_and(|role| role >= Role::Admin)
+        })
+        .cloned()
+        .collect();
+
+    Ok((context, expanded_user, workspace, workspaces))
+}
+
+async fn with_move_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    move_workspace: models::workspace::MoveWorkspaceApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+    if workspace.is_root() {
+        return Err(reject::custom(ResourceError {
+            message: String::from("Root workspaces can't be moved"),
+        }));
+    }
+    // taking it away from its parent is a change to the parent too
+    require_role(&mut conn, &expanded_user, workspace.parent_id, Role::Admin)?;
+
+    // only within the same tree
+    let parent = require_role(&mut conn, &expanded_user, move_workspace.parent_id, Role::Admin)?;
+    if parent.user_id != workspace.user_id {
+        return Err(reject::custom(ResourceError {
+            message: String::from("A workspace can't be moved into someone else's tree"),
+        }));
+    }
+
+    let moved = move_workspace.move_to(&mut conn, &workspace).map_err(|e| match e {
+        // the parent went to the trash in the meantime
+        diesel::result::Error::NotFound => reject::custom(NotFound),
+        e => {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        }
+    })?;
+    if !moved {
+        return Err(reject::custom(ResourceError {
+            message: String::from("A workspace can't be moved inside of itself"),
+        }));
+    }
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_delete_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+    if workspace.is_root() {
+        return Err(reject::custom(ResourceError {
+            message: String::from("Root workspaces can't be deleted"),
+        }));
+    }
+    require_role(&mut conn, &expanded_user, workspace.parent_id, Role::Admin)?;
+
+    workspace.delete(&mut conn).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((workspace.parent_id, context, expanded_user))
+}
+
+async fn with_trash(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        Vec<models::workspace::TrashedWorkspace>,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let trashed = models::workspace::TrashedWorkspace::read_by_user(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((context, expanded_user, trashed))
+}
+
+async fn with_restore_from_trash(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = models::workspace::Workspace::read_trashed_by_id(&mut conn, id).map_err(|e| match e {
+        diesel::result::Error::NotFound => reject::custom(NotFound),
+        e => {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        }
+    })?;
+    if !workspace.is_owned_by(expanded_user.user.id) {
+        tracing::warn!(
+            "User {} tried to restore workspace {} owned by {}",
+            expanded_user.user.id,
+            workspace.id,
+            workspace.user_id
+        );
+        return Err(reject::custom(NotAuthorized));
+    }
+
+    workspace.restore(&mut conn).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_reorder_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    reorder_workspace: models::workspace::ReorderWorkspaceApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = require_role(&mut conn, &expanded_user, id, Role::Editor)?;
+    if workspace.is_root() {
+        return Err(reject::custom(ResourceError {
+            message: String::from("Root workspaces can't be reordered"),
+        }));
+    }
+    require_role(&mut conn, &expanded_user, workspace.parent_id, Role::Editor)?;
+
+    workspace
+        .reorder(&mut conn, reorder_workspace.direction)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((workspace.parent_id, context, expanded_user))
+}
+
+async fn with_revisions(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+        Vec<models::workspace_revision::RevisionWithAuthor>,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let revisions = models::workspace_revision::WorkspaceRevision::read_by_workspace(
+        &mut conn,
+        workspace.workspace.id,
+    )
+    .map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((context, expanded_user, workspace, revisions))
+}
+
+async fn with_share_links(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+        Vec<models::share_link::ShareLink>,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let links = models::share_link::ShareLink::read_by_workspace(&mut conn, workspace.workspace.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((context, expanded_user, workspace, links))
+}
+
+async fn with_new_share_link(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    new_link: models::share_link::NewShareLinkApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+
+    models::share_link::NewShareLink::new(id, new_link)
+        .insert(&mut conn)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_revoke_share_link(
+    id: i32,
+    link_id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+
+    let link = models::share_link::ShareLink::read_by_workspace_and_id(&mut conn, id, link_id)
+        .map_err(|_| reject::custom(NotFound))?;
+
+    link.revoke(&mut conn).map_err(|e| {
+        tracing::error!("{:?}", e);
+        reject::custom(ServerError {
+            message: e.to_string(),
+        })
+    })?;
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_members(
     context: Context,
     expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
 ) -> Result<
     (
         Context,
         models::user::ExpandedUser,
         models::workspace::WorkspaceWithChildren,
+        Vec<models::workspace_member::MemberWithUser>,
     ),
     warp::Rejection,
 > {
     let mut conn = context.db_conn.get_conn();
 
-    let workspace = models::workspace::WorkspaceWithChildren::read_root_by_user(&mut conn, expanded_user.user.id)
+    let members = models::workspace_member::WorkspaceMember::read_by_workspace(&mut conn, workspace.workspace.id)
         .map_err(|e| {
             tracing::error!("{:?}", e);
             reject::custom(ServerError {
@@ -109,18 +847,57 @@ pub async fn with_root_workspace(
             })
         })?;
 
-    Ok((context, expanded_user, workspace.unwrap()))
+    Ok((context, expanded_user, workspace, members))
 }
 
-async fn with_update_workspace(
+async fn with_invite_member(
     id: i32,
     context: Context,
     expanded_user: models::user::ExpandedUser,
-    edit_workspace: models::workspace::EditWorkspaceApi,
+    new_member: models::workspace_member::NewWorkspaceMemberApi,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    let workspace = require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+
+    let invitee = models::user::User::read_by_username(&mut conn, new_member.username.clone())
+        .map_err(|_| {
+            reject::custom(ResourceError {
+                message: format!("There is no user called {}", new_member.username),
+            })
+        })?;
+    if workspace.is_owned_by(invitee.id) {
+        return Err(reject::custom(ResourceError {
+            message: format!("{} already owns this workspace", invitee.username),
+        }));
+    }
+
+    models::workspace_member::NewWorkspaceMember::new(id, invitee.id, new_member.role, expanded_user.user.id)
+        .insert(&mut conn)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((id, context, expanded_user))
+}
+
+async fn with_remove_member(
+    id: i32,
+    member_id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
 ) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    edit_workspace.update(&mut conn, id).map_err(|e| {
+    require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+
+    let member = models::workspace_member::WorkspaceMember::read_by_workspace_and_id(&mut conn, id, member_id)
+        .map_err(|_| reject::custom(NotFound))?;
+
+    member.remove(&mut conn).map_err(|e| {
         tracing::error!("{:?}", e);
         reject::custom(ServerError {
             message: e.to_string(),
@@ -130,21 +907,154 @@ async fn with_update_workspace(
     Ok((id, context, expanded_user))
 }
 
-async fn with_publish_workspace(
+async fn with_shared(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        Vec<models::workspace_member::SharedWorkspace>,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let shared = models::workspace_member::WorkspaceMember::read_shared_with(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((context, expanded_user, shared))
+}
+
+async fn with_revision_diff(
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    diff: models::workspace_revision::DiffRevisionsApi,
+) -> Result<
+    (
+        Context,
+        models::user::ExpandedUser,
+        models::workspace::WorkspaceWithChildren,
+        models::workspace_revision::WorkspaceRevision,
+        models::workspace_revision::WorkspaceRevision,
+    ),
+    warp::Rejection,
+> {
+    let mut conn = context.db_conn.get_conn();
+
+    let from = models::workspace_revision::WorkspaceRevision::read_by_workspace_and_id(
+        &mut conn,
+        workspace.workspace.id,
+        diff.from,
+    )
+    .map_err(|_| reject::custom(NotFound))?;
+    let to = models::workspace_revision::WorkspaceRevision::read_by_workspace_and_id(
+        &mut conn,
+        workspace.workspace.id,
+        diff.to,
+    )
+    .map_err(|_| reject::custom(NotFound))?;
+
+    Ok((context, expanded_user, workspace, from, to))
+}
+
+async fn with_restore_revision(
     id: i32,
+    revision_id: i32,
     context: Context,
     expanded_user: models::user::ExpandedUser,
-    publish_workspace: models::workspace::PublishWorkspaceApi,
 ) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
-    publish_workspace.publish(&mut conn, id).map_err(|e| {
-        tracing::error!("{:?}", e);
-        reject::custom(ServerError {
-            message: e.to_string(),
-        })
+    require_role(&mut conn, &expanded_user, id, Role::Editor)?;
+
+    let revision =
+        models::workspace_revision::WorkspaceRevision::read_by_workspace_and_id(
+            &mut conn,
+            id,
+            revision_id,
+        )
+        .map_err(|_| reject::custom(NotFound))?;
+
+    revision
+        .restore(&mut conn, expanded_user.user.id)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+
+    Ok((id, context, expanded_user))
+}
+
+/// Guard for every route that changes a workspace. Rejects with `NotFound`
+/// when there is no such workspace and `NotAuthorized` when the user owns
+/// it or was let in as anything less than `role`.
+pub fn require_role(
+    conn: &mut PgConnection,
+    expanded_user: &models::user::ExpandedUser,
+    id: i32,
+    role: Role,
+) -> Result<models::workspace::Workspace, warp::Rejection> {
+    let workspace = models::workspace::Workspace::read_by_id(conn, id).map_err(|e| match e {
+        diesel::result::Error::NotFound => reject::custom(NotFound),
+        e => {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        }
     })?;
 
+    let has = models::workspace_member::WorkspaceMember::role_of(conn, expanded_user.user.id, &workspace)
+        .map_err(|e| {
+            tracing::error!("{:?}", e);
+            reject::custom(ServerError {
+                message: e.to_string(),
+            })
+        })?;
+    if has.is_none_or(|has| has < role) {
+        tracing::warn!(
+            "User {} tried to change workspace {} owned by {} without being its {}",
+            expanded_user.user.id,
+            workspace.id,
+            workspace.user_id,
+            role.name()
+        );
+        return Err(reject::custom(NotAuthorized));
+    }
+
+    Ok(workspace)
+}
+
+pub async fn with_editable_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    require_role(&mut conn, &expanded_user, id, Role::Editor)?;
+
+    Ok((id, context, expanded_user))
+}
+
+pub async fn with_administered_workspace(
+    id: i32,
+    context: Context,
+    expanded_user: models::user::ExpandedUser,
+) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
+    let mut conn = context.db_conn.get_conn();
+
+    require_role(&mut conn, &expanded_user, id, Role::Admin)?;
+
     Ok((id, context, expanded_user))
 }
 
@@ -168,9 +1078,10 @@ pub async fn with_workspace(
             reject::custom(ServerError {
                 message: e.to_string(),
             })
-        })?;
+        })?
+        .ok_or(reject::custom(NotFound))?;
 
-    Ok((context, expanded_user, workspace.unwrap()))
+    Ok((context, expanded_user, workspace))
 }
 
 pub async fn with_new_workspace(
@@ -181,9 +1092,12 @@ pub async fn with_new_workspace(
 ) -> Result<(i32, Context, models::user::ExpandedUser), warp::Rejection> {
     let mut conn = context.db_conn.get_conn();
 
+    // part of the parent's tree, whoever adds it
+    let parent = models::workspace::Workspace::read_by_id(&mut conn, parent_id)
+        .map_err(|_| reject::custom(NotFound))?;
     let _new_workspace =
-        models::workspace::NewWorkspace::new(new_workspace, expanded_user.user.id, parent_id)
-            .insert(&mut conn)
+        models::workspace::NewWorkspace::new(new_workspace, parent.user_id, parent_id)
+            .insert_by(&mut conn, expanded_user.user.id)
             .map_err(|e| {
                 tracing::error!("{:?}", e);
                 reject::custom(ServerError {
@@ -229,13 +1143,918 @@ pub async fn insert_root_workspace(
         })
     })?;
     tracing::debug!("Saved Workspace");
+    let author = expanded_user.user.clone();
 
     Ok((
         context,
         expanded_user,
         models::workspace::WorkspaceWithChildren {
+            tree: vec![workspace.clone()],
+            navigable: vec![workspace.clone()],
             workspace,
             children: vec![],
+            author,
+            role: Role::Owner,
+            memberships: vec![],
         },
     ))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) fn test_context() -> Context {
+    let config = std::sync::Arc::new(crate::config::generate_test_config());
+    let db_conn = std::sync::Arc::new(crate::db_conn::DbConn::new(&config.db_path));
+    Context::new(config, db_conn)
+}
+
+#[cfg(test)]
+pub(crate) fn signed_in_user(
+    context: &Context,
+    username: &str,
+) -> (models::user::ExpandedUser, models::workspace::Workspace) {
+    let mut conn = context.db_conn.get_conn();
+    let username = format!("{}{}", username, crate::utils::now().and_utc().timestamp_nanos_opt().unwrap());
+
+    let user = models::user::NewUser::new(models::user::UserCredentialsEncrypted {
+        username: username.clone(),
+        password: crate::utils::encrypt("password"),
+    })
+    .insert(&mut conn)
+    .unwrap();
+    let token = crate::utils::random_token();
+    let session = models::session::NewSession::new(&context.config, user.id, &token, None, false)
+        .insert(&mut conn)
+        .unwrap();
+    let root = models::workspace::NewWorkspace::new(
+        models::workspace::NewWorkspaceApi {
+            name: username,
+            description: String::from("root"),
+            type_id: models::workspace::WorkspaceType::Root as i32,
+        },
+        user.id,
+        -1,
+    )
+    .insert(&mut conn)
+    .unwrap();
+
+    (
+        models::user::ExpandedUser {
+            user,
+            session,
+            token,
+            refreshed_cookie: None,
+        },
+        root,
+    )
+}
+
+#[cfg(test)]
+pub(crate) fn session_cookie(expanded_user: &models::user::ExpandedUser) -> String {
+    format!("session={}", expanded_user.token)
+}
+
+#[cfg(test)]
+pub(crate) fn form_post(path: String, expanded_user: &models::user::ExpandedUser, body: &str) -> warp::test::RequestBuilder {
+    warp::test::request()
+        .method("POST")
+        .path(&path)
+        .header("cookie", session_cookie(expanded_user))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("csrf_token={}&{}", expanded_user.csrf_token(), body))
+}
+
+#[tokio::test]
+async fn test_cross_user_edit_is_rejected() {
+    let context = test_context();
+    let (_alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+
+    let rejection = form_post(
+        format!("/workspace/{}", alice_root.id),
+        &bob,
+        "name=pwned&description=pwned&content=pwned",
+    )
+    .extension(context.clone())
+    .filter(&edit())
+    .await
+    .err()
+    .expect("bob should not be able to edit alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    let mut conn = context.db_conn.get_conn();
+    let workspace = models::workspace::Workspace::read_by_id(&mut conn, alice_root.id).unwrap();
+    assert_eq!(workspace.name, alice_root.name);
+    assert_eq!(workspace.content, alice_root.content);
+}
+
+#[tokio::test]
+async fn test_cross_user_publish_is_rejected() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+
+    let mut conn = context.db_conn.get_conn();
+    let draft = models::workspace::NewWorkspace::new(
+        models::workspace::NewWorkspaceApi {
+            name: String::from("draft"),
+            description: String::from("not ready yet"),
+            type_id: models::workspace::WorkspaceType::Markdown as i32,
+        },
+        alice.user.id,
+        alice_root.id,
+    )
+    .insert(&mut conn)
+    .unwrap();
+
+    let rejection = form_post(format!("/workspace/{}/publish", draft.id), &bob, "is_published=true")
+        .extension(context.clone())
+        .filter(&publish())
+        .await
+        .err()
+        .expect("bob should not be able to publish alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    let workspace = models::workspace::Workspace::read_by_id(&mut conn, draft.id).unwrap();
+    assert!(!workspace.is_published);
+}
+
+#[tokio::test]
+async fn test_cross_user_new_workspace_is_rejected() {
+    let context = test_context();
+    let (_alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+
+    let rejection = form_post(
+        format!("/workspace/{}/new", alice_root.id),
+        &bob,
+        "name=squatter&description=squatter&type_id=2",
+    )
+    .extension(context.clone())
+    .filter(&new())
+    .await
+    .err()
+    .expect("bob should not be able to add to alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+}
+
+#[cfg(test)]
+pub(crate) fn child_workspace(
+    context: &Context,
+    expanded_user: &models::user::ExpandedUser,
+    parent_id: i32,
+    name: &str,
+) -> models::workspace::Workspace {
+    let mut conn = context.db_conn.get_conn();
+    models::workspace::NewWorkspace::new(
+        models::workspace::NewWorkspaceApi {
+            name: name.to_string(),
+            description: name.to_string(),
+            type_id: models::workspace::WorkspaceType::Markdown as i32,
+        },
+        expanded_user.user.id,
+        parent_id,
+    )
+    .insert(&mut conn)
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_move_workspace() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, bob_root) = signed_in_user(&context, "bob");
+    let parent = child_workspace(&context, &alice, alice_root.id, "parent");
+    let child = child_workspace(&context, &alice, parent.id, "child");
+    let sibling = child_workspace(&context, &alice, alice_root.id, "sibling");
+
+    let filter = move_workspace();
+    let move_to = |id: i32, parent_id: i32, user: &models::user::ExpandedUser| {
+        form_post(format!("/workspace/{}/move", id), user, &format!("parent_id={}", parent_id))
+            .extension(context.clone())
+            .filter(&filter)
+    };
+
+    // under its own child
+    let rejection = move_to(parent.id, child.id, &alice).await.err().unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    // under itself
+    let rejection = move_to(parent.id, parent.id, &alice).await.err().unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    // the root stays put
+    let rejection = move_to(alice_root.id, sibling.id, &alice).await.err().unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    // into someone else's tree
+    let rejection = move_to(child.id, bob_root.id, &alice).await.err().unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+    // someone else's workspace
+    let rejection = move_to(child.id, bob_root.id, &bob).await.err().unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    let (_, _, moved) = match move_to(child.id, sibling.id, &alice).await {
+        Ok(res) => res,
+        Err(e) => panic!("alice should be able to move their own workspace: {:?}", e),
+    };
+    assert_eq!(moved.workspace.parent_id, sibling.id);
+}
+
+#[tokio::test]
+async fn test_reorder_workspace() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let first = child_workspace(&context, &alice, alice_root.id, "first");
+    let second = child_workspace(&context, &alice, alice_root.id, "second");
+    let third = child_workspace(&context, &alice, alice_root.id, "third");
+
+    let filter = reorder();
+    let reorder_to = |id: i32, direction: &str, user: &models::user::ExpandedUser| {
+        form_post(format!("/workspace/{}/reorder", id), user, &format!("direction={}", direction))
+            .extension(context.clone())
+            .filter(&filter)
+    };
+    let order = |parent: &models::workspace::WorkspaceWithChildren| {
+        parent
+            .children
+            .iter()
+            .map(|child| child.id)
+            .collect::<Vec<i32>>()
+    };
+
+    let (_, _, parent) = reorder_to(third.id, "up", &alice).await.unwrap();
+    assert_eq!(parent.workspace.id, alice_root.id);
+    assert_eq!(order(&parent), vec![first.id, third.id, second.id]);
+
+    // already first, nothing moves
+    let (_, _, parent) = reorder_to(first.id, "up", &alice).await.unwrap();
+    assert_eq!(order(&parent), vec![first.id, third.id, second.id]);
+
+    let (_, _, parent) = reorder_to(first.id, "down", &alice).await.unwrap();
+    assert_eq!(order(&parent), vec![third.id, first.id, second.id]);
+
+    let rejection = reorder_to(second.id, "up", &bob).await.err().unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+    let rejection = reorder_to(alice_root.id, "down", &alice).await.err().unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+
+    // moved in workspaces go last
+    let other = child_workspace(&context, &alice, first.id, "other");
+    let mut conn = context.db_conn.get_conn();
+    assert!(models::workspace::MoveWorkspaceApi { parent_id: alice_root.id }
+        .move_to(&mut conn, &other)
+        .unwrap());
+    let parent =
+        models::workspace::WorkspaceWithChildren::read_root_by_user(&mut conn, alice.user.id)
+            .unwrap()
+            .unwrap();
+    assert_eq!(order(&parent), vec![third.id, first.id, second.id, other.id]);
+}
+
+#[tokio::test]
+async fn test_trash_and_restore() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let parent = child_workspace(&context, &alice, alice_root.id, "parent");
+    let child = child_workspace(&context, &alice, parent.id, "child");
+    let grandchild = child_workspace(&context, &alice, child.id, "grandchild");
+
+    let rejection = form_post(format!("/workspace/{}/delete", alice_root.id), &alice, "")
+        .extension(context.clone())
+        .filter(&delete())
+        .await
+        .err()
+        .expect("the root should not be deletable");
+    assert!(rejection.find::<ResourceError>().is_some());
+    let rejection = form_post(format!("/workspace/{}/delete", child.id), &bob, "")
+        .extension(context.clone())
+        .filter(&delete())
+        .await
+        .err()
+        .expect("bob should not be able to delete alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    let (_, _, shown) = form_post(format!("/workspace/{}/delete", child.id), &alice, "")
+        .extension(context.clone())
+        .filter(&delete())
+        .await
+        .unwrap();
+    assert_eq!(shown.workspace.id, parent.id);
+    assert!(shown.children.is_empty());
+
+    let mut conn = context.db_conn.get_conn();
+    assert!(models::workspace::Workspace::read_by_id(&mut conn, grandchild.id).is_err());
+
+    // the parent goes too, later, on its own
+    parent.delete(&mut conn).unwrap();
+    let trashed = models::workspace::TrashedWorkspace::read_by_user(&mut conn, alice.user.id).unwrap();
+    assert_eq!(trashed.len(), 2);
+    let trashed_child = trashed.iter().find(|t| t.workspace.id == child.id).unwrap();
+    assert_eq!(
+        trashed_child.subtree.iter().map(|(_, w)| w.id).collect::<Vec<i32>>(),
+        vec![grandchild.id]
+    );
+
+    let rejection = form_post(format!("/trash/{}/restore", child.id), &bob, "")
+        .extension(context.clone())
+        .filter(&restore_from_trash())
+        .await
+        .err()
+        .expect("bob should not be able to restore alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    // its parent is still in the trash, so it lands under the root
+    let (_, _, restored) = form_post(format!("/trash/{}/restore", child.id), &alice, "")
+        .extension(context.clone())
+        .filter(&restore_from_trash())
+        .await
+        .unwrap();
+    assert_eq!(restored.workspace.parent_id, alice_root.id);
+    assert_eq!(restored.children.iter().map(|w| w.id).collect::<Vec<i32>>(), vec![grandchild.id]);
+    assert!(models::workspace::Workspace::read_trashed_by_id(&mut conn, parent.id).is_ok());
+
+    // nothing purged before its time, then gone for good
+    models::workspace::Workspace::purge_trash(&mut conn, alice_root.created_at).unwrap();
+    assert!(models::workspace::Workspace::read_trashed_by_id(&mut conn, parent.id).is_ok());
+    models::workspace::Workspace::purge_trash(&mut conn, crate::utils::now()).unwrap();
+    assert!(models::workspace::Workspace::read_trashed_by_id(&mut conn, parent.id).is_err());
+    assert!(models::workspace::Workspace::read_by_id(&mut conn, child.id).is_ok());
+}
+
+#[tokio::test]
+async fn test_workspace_tree_and_breadcrumbs() {
+    use crate::schema::workspace;
+    use diesel::prelude::*;
+
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let parent = child_workspace(&context, &alice, alice_root.id, "parent");
+    let child = child_workspace(&context, &alice, parent.id, "child");
+    let grandchild = child_workspace(&context, &alice, child.id, "grandchild");
+    let trashed = child_workspace(&context, &alice, alice_root.id, "trashed");
+    child_workspace(&context, &alice, trashed.id, "under trashed");
+
+    let mut conn = context.db_conn.get_conn();
+    trashed.delete(&mut conn).unwrap();
+    // named before names were sanitized on the way in
+    diesel::update(workspace::table.find(child.id))
+        .set(workspace::name.eq("<i>child</i>"))
+        .execute(&mut conn)
+        .unwrap();
+    models::workspace::PublishWorkspaceApi { is_published: true }
+        .publish(&mut conn, parent.id)
+        .unwrap();
+    models::workspace::PublishWorkspaceApi { is_published: true }
+        .publish(&mut conn, grandchild.id)
+        .unwrap();
+
+    let (_, _, shown) = warp::test::request()
+        .path(&format!("/workspace/{}", grandchild.id))
+        .header("cookie", session_cookie(&alice))
+        .extension(context.clone())
+        .filter(&workspace())
+        .await
+        .unwrap();
+    let mut ids: Vec<i32> = shown.tree.iter().map(|w| w.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![alice_root.id, parent.id, child.id, grandchild.id]);
+
+    let ancestors: Vec<i32> = shown
+        .workspace
+        .ancestors(&shown.tree)
+        .into_iter()
+        .map(|w| w.id)
+        .collect();
+    assert_eq!(ancestors, vec![alice_root.id, parent.id, child.id]);
+    let breadcrumbs = shown.breadcrumbs();
+    assert!(breadcrumbs.contains(&format!("/workspace/{}", child.id)));
+    assert!(shown.tree_html().contains("<strong>grandchild</strong>"));
+    assert!(!breadcrumbs.contains("<i>"));
+    assert!(!shown.tree_html().contains("<i>"));
+
+    // the draft in between hides what is under it from visitors
+    let public: Vec<i32> = models::workspace::Workspace::published_only(&shown.tree)
+        .iter()
+        .map(|w| w.id)
+        .collect();
+    assert_eq!(public, vec![alice_root.id, parent.id]);
+}
+
+#[tokio::test]
+async fn test_preludes_and_styles_are_inherited() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let section = child_workspace(&context, &alice, alice_root.id, "section");
+    let article = child_workspace(&context, &alice, section.id, "article");
+
+    let filter = edit();
+    let edit_to = |id: i32, body: &str| {
+        form_post(format!("/workspace/{}", id), &alice, body)
+            .extension(context.clone())
+            .filter(&filter)
+    };
+    edit_to(
+        section.id,
+        "name=section&description=section&content=&prelude=(fun+[shout+x]+[concat+x+%22!%22])&style=h1+%7B+color%3A+red%3B+%7D",
+    )
+    .await
+    .unwrap();
+    let (_, _, shown) = edit_to(
+        article.id,
+        "name=article&description=article&content=|(p+(shout+%22hi%22))|%0A&prelude=&style=h1+%7B+color%3A+blue%3B+%7D",
+    )
+    .await
+    .unwrap();
+
+    let preludes = shown.preludes();
+    assert_eq!(
+        preludes.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>(),
+        vec!["global prelude", "prelude", "prelude of section"]
+    );
+    let rendered = shown
+        .workspace
+        .render_preview(&context, &alice.user, &shown.tree, models::workspace::Audience::Owner)
+        .await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(rendered.html.contains("<p>hi!</p>"));
+
+    let stylesheets = shown.stylesheets();
+    assert_eq!(stylesheets.len(), 2);
+    assert!(stylesheets[0].contains(&format!("/workspace/{}/style.css", section.id)));
+    assert!(stylesheets[1].contains(&format!("/workspace/{}/style.css", article.id)));
+}
+
+#[tokio::test]
+async fn test_include_workspace() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, bob_root) = signed_in_user(&context, "bob");
+    let intro = child_workspace(&context, &alice, alice_root.id, "intro");
+    let essay = child_workspace(&context, &alice, alice_root.id, "essay");
+    let draft = child_workspace(&context, &bob, bob_root.id, "draft");
+    let published = child_workspace(&context, &bob, bob_root.id, "published");
+
+    let mut conn = context.db_conn.get_conn();
+    let set_content = |conn: &mut PgConnection, workspace: &models::workspace::Workspace, content: &str| {
+        models::workspace::EditWorkspaceApi {
+            name: workspace.name.clone(),
+            description: workspace.description.clone(),
+            content: Some(content.to_string()),
+            prelude: None,
+            style: None,
+        }
+        .update(conn, workspace.id, workspace.user_id)
+        .unwrap()
+    };
+    let render = |workspace: models::workspace::Workspace, audience| {
+        let context = context.clone();
+        let mut conn = context.db_conn.get_conn();
+        let tree = models::workspace::Workspace::read_tree_by_user(&mut conn, alice.user.id).unwrap();
+        let author = alice.user.clone();
+        async move { workspace.render_preview(&context, &author, &tree, audience).await }
+    };
+
+    set_content(&mut conn, &intro, "# Intro\n");
+    set_content(&mut conn, &draft, "# Draft\n");
+    set_content(&mut conn, &published, "# Published\n");
+    models::workspace::PublishWorkspaceApi { is_published: true }
+        .publish(&mut conn, published.id)
+        .unwrap();
+
+    let essay = set_content(&mut conn, &essay, &format!("|(include {})|\n", intro.id));
+    let rendered = render(essay.clone(), models::workspace::Audience::Owner).await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(rendered.html.contains("<h1>Intro</h1>"));
+    assert_eq!(rendered.includes, vec![intro.id]);
+
+    // alice's unpublished intro is only hers to include
+    let rendered = render(essay.clone(), models::workspace::Audience::Public).await;
+    assert!(!rendered.diagnostics.is_empty());
+    assert!(!rendered.html.contains("<h1>Intro</h1>"));
+
+    let intro = set_content(&mut conn, &intro, &format!("|(include {})|\n", essay.id));
+    let rendered = render(intro.clone(), models::workspace::Audience::Owner).await;
+    assert!(
+        rendered.diagnostics.iter().any(|d| d.message.contains("includes itself")),
+        "{:?}",
+        rendered.diagnostics
+    );
+
+    let essay = set_content(&mut conn, &essay, &format!("|(include {})|\n", draft.id));
+    let rendered = render(essay.clone(), models::workspace::Audience::Owner).await;
+    assert!(
+        rendered.diagnostics.iter().any(|d| d.message.contains("no workspace")),
+        "{:?}",
+        rendered.diagnostics
+    );
+
+    let essay = set_content(&mut conn, &essay, &format!("|(include {})|\n", published.id));
+    let rendered = render(essay, models::workspace::Audience::Public).await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(rendered.html.contains("<h1>Published</h1>"));
+}
+
+#[tokio::test]
+async fn test_workspace_tree_values() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let section = child_workspace(&context, &alice, alice_root.id, "section");
+    let draft = child_workspace(&context, &alice, section.id, "draft");
+    let published = child_workspace(&context, &alice, section.id, "published");
+    let sibling = child_workspace(&context, &alice, alice_root.id, "sibling");
+
+    let mut conn = context.db_conn.get_conn();
+    for id in [section.id, published.id] {
+        models::workspace::PublishWorkspaceApi { is_published: true }
+            .publish(&mut conn, id)
+            .unwrap();
+    }
+    let section = models::workspace::EditWorkspaceApi {
+        name: section.name.clone(),
+        description: section.description.clone(),
+        content: Some(String::from(
+            "|(ul (append (cons \"\" (map children (\\ [c] [li (a (url-of c) (title-of c))])))))|\n\
+             |(p (append (cons \"\" (map (join parent siblings) title-of))))|\n\
+             |(p (title-of (head author)))|\n",
+        )),
+        prelude: None,
+        style: None,
+    }
+    .update(&mut conn, section.id, alice.user.id)
+    .unwrap();
+    let tree = models::workspace::Workspace::read_tree_by_user(&mut conn, alice.user.id).unwrap();
+
+    let rendered = section
+        .render_preview(&context, &alice.user, &tree, models::workspace::Audience::Owner)
+        .await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(rendered
+        .html
+        .contains(&format!("<li><a href=\"/workspace/{}\">draft</a></li>", draft.id)));
+    assert!(rendered
+        .html
+        .contains(&format!("<li><a href=\"/workspace/{}\">published</a></li>", published.id)));
+    assert!(rendered.html.contains(&format!("<p>{}sibling</p>", alice_root.name)));
+    assert!(rendered.html.contains(&format!("<p>{}</p>", alice.user.username)));
+
+    // the public sees the published children, at their public addresses
+    let rendered = section
+        .render_preview(&context, &alice.user, &tree, models::workspace::Audience::Public)
+        .await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(!rendered.html.contains("draft"));
+    assert!(rendered.html.contains(&format!(
+        "<li><a href=\"/{}/workspace/{}\">published</a></li>",
+        alice.user.username, published.id
+    )));
+    assert!(!rendered.html.contains(&sibling.name));
+}
+
+#[tokio::test]
+async fn test_lisp_values_are_escaped() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let name = "say \"hi\") (def [title] \"gotcha";
+    let description = "back\\slash |(p \"pipes\")|\nand a newline";
+    let workspace = child_workspace(&context, &alice, alice_root.id, "placeholder");
+    child_workspace(&context, &alice, workspace.id, name);
+
+    let mut conn = context.db_conn.get_conn();
+    let workspace = models::workspace::EditWorkspaceApi {
+        name: name.to_string(),
+        description: description.to_string(),
+        content: Some(String::from(
+            "|(p title)|\n|(p description)|\n|(p (title-of (head children)))|\n",
+        )),
+        prelude: None,
+        style: None,
+    }
+    .update(&mut conn, workspace.id, alice.user.id)
+    .unwrap();
+    let tree = models::workspace::Workspace::read_tree_by_user(&mut conn, alice.user.id).unwrap();
+
+    let rendered = workspace
+        .render_preview(&context, &alice.user, &tree, models::workspace::Audience::Owner)
+        .await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    // sanitizing the output turns the entities back into the characters
+    assert_eq!(rendered.html.matches(&format!("<p>{}</p>", name)).count(), 2);
+    assert!(rendered.html.contains(&format!("<p>{}</p>", description)));
+    assert!(!rendered.html.contains("<p>gotcha</p>"));
+}
+
+#[tokio::test]
+async fn test_content_is_kept_and_output_sanitized() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let workspace = child_workspace(&context, &alice, alice_root.id, "article");
+
+    let (_, _, shown) = form_post(
+        format!("/workspace/{}", workspace.id),
+        &alice,
+        "name=article&description=article&content=|(p+(if+(<+1+2)+[%22less%22]+[%22more%22]))|%0A\
+         |(concat+%22<scr%22+%22ipt>alert(1)</scr%22+%22ipt>%22)|%0A\
+         |(a+%22javascript:alert(2)%22+%22click%22)|%0A",
+    )
+    .extension(context.clone())
+    .filter(&edit())
+    .await
+    .unwrap();
+
+    let saved = shown.workspace.content.clone().unwrap();
+    assert!(saved.contains("(< 1 2)"));
+    assert!(saved.contains("<scr"));
+
+    let rendered = shown
+        .workspace
+        .render_content(&context, &alice.user, &shown.tree, models::workspace::Audience::Owner)
+        .await;
+    assert!(rendered.diagnostics.is_empty(), "{:?}", rendered.diagnostics);
+    assert!(rendered.html.contains("<p>less</p>"));
+    assert!(!rendered.html.contains("<script"));
+    assert!(!rendered.html.contains("alert"));
+    assert!(rendered.html.contains("click"));
+}
+
+#[tokio::test]
+async fn test_preview_renders_without_saving() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+
+    let body = "name=draft&description=draft&content=%23+Previewed%0A";
+    let (_, _, workspace) = match form_post(format!("/workspace/{}/preview", alice_root.id), &alice, body)
+        .extension(context.clone())
+        .filter(&preview())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => panic!("alice should be able to preview their own workspace: {:?}", e),
+    };
+    let rendered = workspace
+        .workspace
+        .render_preview(&context, &alice.user, &workspace.tree, models::workspace::Audience::Owner)
+        .await;
+    assert!(rendered.html.contains("<h1>Previewed</h1>"));
+
+    let mut conn = context.db_conn.get_conn();
+    let saved = models::workspace::Workspace::read_by_id(&mut conn, alice_root.id).unwrap();
+    assert_eq!(saved.content, alice_root.content);
+
+    let rejection = form_post(format!("/workspace/{}/preview", alice_root.id), &bob, body)
+        .extension(context.clone())
+        .filter(&preview())
+        .await
+        .err()
+        .expect("bob should not be able to preview alice's workspace");
+    assert!(rejection.find::<NotAuthorized>().is_some());
+}
+
+#[tokio::test]
+async fn test_owner_can_edit() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+
+    let (_, _, workspace) = match form_post(
+        format!("/workspace/{}", alice_root.id),
+        &alice,
+        "name=mine&description=still+mine&content=hello",
+    )
+    .extension(context.clone())
+    .filter(&edit())
+    .await
+    {
+        Ok(res) => res,
+        Err(e) => panic!("alice should be able to edit their own workspace: {:?}", e),
+    };
+
+    assert_eq!(workspace.workspace.name, "mine");
+    assert_eq!(workspace.workspace.content, Some(String::from("hello")));
+}
+
+#[tokio::test]
+async fn test_forms_need_the_sessions_csrf_token() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let child = child_workspace(&context, &alice, alice_root.id, "child");
+    let (publish_filter, delete_filter) = (publish(), delete());
+    let post = |path: String, body: String| {
+        warp::test::request()
+            .method("POST")
+            .path(&path)
+            .header("cookie", session_cookie(&alice))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+            .extension(context.clone())
+    };
+
+    // missing, or someone else's, is as good as another site posting
+    for body in [
+        String::from("is_published=true"),
+        format!("csrf_token={}&is_published=true", bob.csrf_token()),
+    ] {
+        let rejection = post(format!("/workspace/{}/publish", child.id), body)
+            .filter(&publish_filter)
+            .await
+            .err()
+            .unwrap();
+        assert!(rejection.find::<NotAuthorized>().is_some());
+    }
+    let rejection = post(format!("/workspace/{}/delete", child.id), String::new())
+        .filter(&delete_filter)
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    let (_, _, published) = post(
+        format!("/workspace/{}/publish", child.id),
+        format!("csrf_token={}&is_published=true", alice.csrf_token()),
+    )
+    .filter(&publish_filter)
+    .await
+    .unwrap();
+    assert!(published.workspace.is_published);
+
+    // and the forms on the page carry it
+    let (_, _, root) = warp::test::request()
+        .path(&format!("/workspace/{}", alice_root.id))
+        .header("cookie", session_cookie(&alice))
+        .extension(context.clone())
+        .filter(&workspace())
+        .await
+        .unwrap();
+    let html = root.actions(&alice.csrf_token(), false);
+    assert!(html.contains(&format!(r#"name="csrf_token" value="{}""#, alice.csrf_token())));
+    assert_ne!(alice.csrf_token(), bob.csrf_token());
+}
+
+#[tokio::test]
+async fn test_collaborators() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let team = child_workspace(&context, &alice, alice_root.id, "team");
+    let notes = child_workspace(&context, &alice, team.id, "notes");
+    let private = child_workspace(&context, &alice, alice_root.id, "private");
+
+    let (view_filter, invite_filter, edit_filter) = (workspace(), invite_member(), edit());
+    let view = |id: i32, user: &models::user::ExpandedUser| {
+        warp::test::request()
+            .path(&format!("/workspace/{}", id))
+            .header("cookie", session_cookie(user))
+            .extension(context.clone())
+            .filter(&view_filter)
+    };
+    let invite = |id: i32, user: &models::user::ExpandedUser, body: String| {
+        form_post(format!("/workspace/{}/members", id), user, &body)
+            .extension(context.clone())
+            .filter(&invite_filter)
+    };
+    let edit_notes = |user: &models::user::ExpandedUser| {
+        form_post(format!("/workspace/{}", notes.id), user, "name=notes&description=notes&content=by+bob")
+            .extension(context.clone())
+            .filter(&edit_filter)
+    };
+
+    // not let in yet
+    let rejection = view(team.id, &bob).await.err().unwrap();
+    assert!(rejection.find::<NotFound>().is_some());
+
+    let rejection = invite(team.id, &alice, String::from("username=nobody-at-all&role=viewer"))
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<ResourceError>().is_some());
+    // owners aren't made, only invited
+    assert!(invite(team.id, &alice, format!("username={}&role=owner", bob.user.username))
+        .await
+        .is_err());
+
+    // viewers see the subtree they were let into and nothing around it
+    let (_, _, team_page, members) = invite(team.id, &alice, format!("username={}&role=viewer", bob.user.username))
+        .await
+        .unwrap();
+    assert_eq!(team_page.role, Role::Owner);
+    assert_eq!(members.len(), 1);
+    let (_, _, shown) = view(notes.id, &bob).await.unwrap();
+    assert_eq!(shown.role, Role::Viewer);
+    assert_eq!(shown.author.id, alice.user.id);
+    let mut navigable: Vec<i32> = shown.navigable.iter().map(|w| w.id).collect();
+    navigable.sort();
+    assert_eq!(navigable, vec![team.id, notes.id]);
+    assert!(!shown.tree_html().contains("private"));
+    assert!(view(private.id, &bob).await.is_err());
+    assert!(edit_notes(&bob).await.err().unwrap().find::<NotAuthorized>().is_some());
+    let rejection = invite(team.id, &bob, format!("username={}&role=admin", bob.user.username))
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    // inviting again changes the role, editors' edits are theirs
+    invite(team.id, &alice, format!("username={}&role=editor", bob.user.username))
+        .await
+        .unwrap();
+    let (_, _, edited) = edit_notes(&bob).await.unwrap();
+    assert_eq!(edited.workspace.content, Some(String::from("by bob")));
+    let mut conn = context.db_conn.get_conn();
+    let revisions =
+        models::workspace_revision::WorkspaceRevision::read_by_workspace(&mut conn, notes.id).unwrap();
+    assert_eq!(revisions[0].author, bob.user.username);
+
+    // what they add stays in the owner's tree, with no markup for alice
+    let (_, _, with_new) = form_post(
+        format!("/workspace/{}/new", notes.id),
+        &bob,
+        "name=added%3Cimg+src%3Dx+onerror%3Dalert(1)%3E&description=added&type_id=2",
+    )
+    .extension(context.clone())
+    .filter(&new())
+    .await
+    .unwrap();
+    assert!(with_new.children.iter().all(|child| child.user_id == alice.user.id));
+    assert!(with_new.children.iter().all(|child| child.name == "added"));
+
+    let rejection = form_post(format!("/workspace/{}/delete", notes.id), &bob, "")
+        .extension(context.clone())
+        .filter(&delete())
+        .await
+        .err()
+        .unwrap();
+    assert!(rejection.find::<NotAuthorized>().is_some());
+
+    // once removed, it's gone again
+    let member = &members[0].member;
+    form_post(format!("/workspace/{}/members/{}/remove", team.id, member.id), &alice, "")
+        .extension(context.clone())
+        .filter(&remove_member())
+        .await
+        .unwrap();
+    assert!(view(notes.id, &bob).await.is_err());
+}
+
+#[tokio::test]
+async fn test_members_only_render_what_they_were_let_into() {
+    let context = test_context();
+    let (alice, alice_root) = signed_in_user(&context, "alice");
+    let (bob, _) = signed_in_user(&context, "bob");
+    let team = child_workspace(&context, &alice, alice_root.id, "team");
+    let secret = child_workspace(&context, &alice, alice_root.id, "secret");
+    let published = child_workspace(&context, &alice, alice_root.id, "published");
+
+    let mut conn = context.db_conn.get_conn();
+    models::workspace::EditWorkspaceApi {
+        name: secret.name.clone(),
+        description: secret.description.clone(),
+        content: Some(String::from("# Secret plans\n")),
+        prelude: None,
+        style: None,
+    }
+    .update(&mut conn, secret.id, alice.user.id)
+    .unwrap();
+    models::workspace::PublishWorkspaceApi { is_published: true }
+        .publish(&mut conn, published.id)
+        .unwrap();
+    models::workspace_member::NewWorkspaceMember::new(team.id, bob.user.id, Role::Editor, alice.user.id)
+        .insert(&mut conn)
+        .unwrap();
+
+    let filter = preview().and_then(crate::handlers::workspace::preview);
+    let preview_as = |user: &models::user::ExpandedUser, content: String| {
+        let content: String = content
+            .bytes()
+            .map(|b| match b.is_ascii_alphanumeric() {
+                true => (b as char).to_string(),
+                false => format!("%{:02X}", b),
+            })
+            .collect();
+        let body = format!("name=team&description=team&content={}", content);
+        let request = form_post(format!("/workspace/{}/preview", team.id), user, &body);
+        let (context, filter) = (context.clone(), filter.clone());
+        async move {
+            let res = request.extension(context).reply(&filter).await;
+            assert_eq!(res.status(), 200);
+            String::from_utf8(res.body().to_vec()).unwrap()
+        }
+    };
+
+    // the owner can include their sibling draft, an editor of the team can't
+    let include = format!("|(include {})|\n", secret.id);
+    assert!(preview_as(&alice, include.clone()).await.contains("Secret plans"));
+    let html = preview_as(&bob, include).await;
+    assert!(!html.contains("Secret plans"));
+    assert!(html.contains(&format!("There is no workspace {} to include", secret.id)));
+
+    // nor see it among the siblings, the published one is at its public address
+    let siblings = String::from(
+        "|(ul (append (cons \"\" (map siblings (\\ [s] [li (a (url-of s) (title-of s))])))))|\n",
+    );
+    let html = preview_as(&alice, siblings.clone()).await;
+    assert!(html.contains(&format!("<a href=\"/workspace/{}\">secret</a>", secret.id)));
+    let html = preview_as(&bob, siblings).await;
+    assert!(!html.contains("secret"));
+    assert!(html.contains(&format!(
+        "<a href=\"/{}/workspace/{}\">published</a>",
+        alice.user.username, published.id
+    )));
+}
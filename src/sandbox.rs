@@ -0,0 +1,446 @@
+use crate::config::Config;
+use bebop_lang::lisp::{env::Lenv, parser::SyntaxError, Lerr, Lfun, Lval};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+// programs nested deeper than this are refused before parsing,
+// the bebop parser and evaluator recurse on every bracket
+const MAX_NESTING: usize = 512;
+// the depth guard trips once this share of the thread's stack is used
+const STACK_GUARD_RATIO: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub max_steps: usize,
+    pub max_output: usize,
+    pub stack_size: usize,
+    pub timeout: Duration,
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Self {
+        Limits {
+            max_steps: config.bebop_max_steps,
+            max_output: config.bebop_max_output_kb * 1024,
+            stack_size: config.bebop_stack_kb * 1024,
+            timeout: Duration::from_millis(config.bebop_timeout_ms),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecutionError {
+    Steps(usize),
+    Depth,
+    Output(usize),
+    Timeout(Duration),
+    Busy,
+    Crashed,
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::Steps(steps) => write!(
+                f,
+                "This program exceeded its budget of {} steps. Check for recursion that never reaches its base case.",
+                steps
+            ),
+            ExecutionError::Depth => write!(
+                f,
+                "This program exceeded its budget for nesting and recursion depth."
+            ),
+            ExecutionError::Output(bytes) => write!(
+                f,
+                "This program exceeded its budget of {}KB of output.",
+                bytes / 1024
+            ),
+            ExecutionError::Timeout(timeout) => write!(
+                f,
+                "This program exceeded its budget of {}ms of running time.",
+                timeout.as_millis()
+            ),
+            ExecutionError::Busy => write!(
+                f,
+                "Too many programs are running right now, please try again in a moment."
+            ),
+            ExecutionError::Crashed => write!(f, "This program crashed the interpreter."),
+        }
+    }
+}
+
+/// Runs Bebop programs off the async workers. Each program gets its own
+/// thread with a known stack size, at most `bebop_max_workers` at a time,
+/// and is metered so it stops itself once it runs out of budget.
+#[derive(Debug)]
+pub struct Sandbox {
+    limits: Limits,
+    permits: Arc<Semaphore>,
+}
+
+impl Sandbox {
+    pub fn new(config: &Config) -> Self {
+        Sandbox {
+            limits: Limits::from_config(config),
+            permits: Arc::new(Semaphore::new(config.bebop_max_workers)),
+        }
+    }
+
+    pub async fn run<F, T>(&self, job: F) -> Result<T, ExecutionError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit =
+            tokio::time::timeout(self.limits.timeout, self.permits.clone().acquire_owned())
+                .await
+                .map_err(|_| ExecutionError::Busy)?
+                .map_err(|_| ExecutionError::Busy)?;
+
+        let limits = self.limits.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        std::thread::Builder::new()
+            .name(String::from("bebop"))
+            .stack_size(limits.stack_size)
+            .spawn(move || {
+                // hold the permit until the program is really done
+                let _permit = permit;
+                let _ = tx.send(metered(&limits, job));
+            })
+            .map_err(|e| {
+                tracing::error!("Could not start bebop thread {:?}", e);
+                ExecutionError::Crashed
+            })?;
+
+        // the meter enforces the deadline itself, this only covers a
+        // single builtin call that takes too long on its own
+        match tokio::time::timeout(self.limits.timeout * 2, rx).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => Err(ExecutionError::Crashed),
+            Err(_) => Err(ExecutionError::Timeout(self.limits.timeout)),
+        }
+    }
+}
+
+struct Meter {
+    limits: Limits,
+    steps: usize,
+    deadline: Instant,
+    stack_base: usize,
+    exceeded: Option<ExecutionError>,
+}
+
+thread_local! {
+    static METER: RefCell<Option<Meter>> = const { RefCell::new(None) };
+}
+
+fn metered<F, T>(limits: &Limits, job: F) -> Result<T, ExecutionError>
+where
+    F: FnOnce() -> T,
+{
+    let stack_marker = 0_u8;
+    METER.with(|meter| {
+        *meter.borrow_mut() = Some(Meter {
+            limits: limits.clone(),
+            steps: 0,
+            deadline: Instant::now() + limits.timeout,
+            stack_base: &stack_marker as *const u8 as usize,
+            exceeded: None,
+        })
+    });
+
+    let output = job();
+
+    let meter = METER.with(|meter| meter.borrow_mut().take());
+    match meter.and_then(|meter| meter.exceeded) {
+        Some(e) => Err(e),
+        None => Ok(output),
+    }
+}
+
+// checks the budget, recording the first thing to run out
+fn charge(output_size: Option<usize>) -> Result<(), ExecutionError> {
+    METER.with(|meter| {
+        let mut meter = meter.borrow_mut();
+        let meter = match meter.as_mut() {
+            Some(meter) => meter,
+            // not running inside the sandbox
+            None => return Ok(()),
+        };
+
+        if let Some(e) = &meter.exceeded {
+            return Err(e.clone());
+        }
+
+        meter.steps += 1;
+        let stack_marker = 0_u8;
+        let stack_used = meter
+            .stack_base
+            .abs_diff(&stack_marker as *const u8 as usize);
+
+        let exceeded = if meter.steps > meter.limits.max_steps {
+            Some(ExecutionError::Steps(meter.limits.max_steps))
+        } else if stack_used > meter.limits.stack_size / STACK_GUARD_RATIO {
+            Some(ExecutionError::Depth)
+        } else if output_size.unwrap_or(0) > meter.limits.max_output {
+            Some(ExecutionError::Output(meter.limits.max_output))
+        } else if Instant::now() > meter.deadline {
+            Some(ExecutionError::Timeout(meter.limits.timeout))
+        } else {
+            None
+        };
+
+        match exceeded {
+            Some(e) => {
+                meter.exceeded = Some(e.clone());
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    })
+}
+
+fn record(e: ExecutionError) {
+    METER.with(|meter| {
+        if let Some(meter) = meter.borrow_mut().as_mut() {
+            meter.exceeded.get_or_insert(e);
+        }
+    })
+}
+
+// the interpreter's builtins we wrap, taken from a stock environment
+struct Builtins {
+    lambda: Lfun,
+    r#if: Lfun,
+    eval: Lfun,
+    concat: Lfun,
+    join: Lfun,
+    die: Lfun,
+}
+
+fn builtins() -> &'static Builtins {
+    static BUILTINS: OnceLock<Builtins> = OnceLock::new();
+    BUILTINS.get_or_init(|| {
+        let env = bebop_lang::lisp::env::init_env();
+        let get = |sym: &str| match env.get(sym) {
+            Some(Lval::Fun(fun)) => fun,
+            _ => panic!("bebop is missing the {} builtin", sym),
+        };
+        Builtins {
+            lambda: get("\\"),
+            r#if: get("if"),
+            eval: get("eval"),
+            concat: get("concat"),
+            join: get("join"),
+            die: get("die"),
+        }
+    })
+}
+
+/// An error for a builtin to fail with. Lerr can't be built outside of
+/// bebop, so the stock `die` makes it.
+pub fn error(env: &mut Lenv, message: String) -> Lerr {
+    match (builtins().die)(env, vec![Lval::Str(message)]) {
+        Err(lerr) => lerr,
+        Ok(_) => unreachable!("die always fails"),
+    }
+}
+
+fn interrupt(env: &mut Lenv, e: ExecutionError) -> Lerr {
+    error(env, e.to_string())
+}
+
+fn metered_step(env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    charge(None).map_err(|e| interrupt(env, e))?;
+    Ok(Lval::Fun(identity))
+}
+
+fn identity(_env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    Ok(operands.pop().unwrap_or(Lval::Sexpr(vec![])))
+}
+
+// Lambda calls never pass through a builtin, so each body is rewritten to
+// start with `((step) first-expr)`, which charges a step before anything
+// else in the body is evaluated. The builtins are spliced in as values so
+// programs can't redefine them.
+fn metered_lambda(env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if let Some(Lval::Qexpr(body)) = operands.get_mut(1) {
+        if !body.is_empty() {
+            let first = body.remove(0);
+            body.insert(
+                0,
+                Lval::Sexpr(vec![Lval::Sexpr(vec![Lval::Fun(metered_step)]), first]),
+            );
+        }
+    }
+    (builtins().lambda)(env, operands)
+}
+
+fn metered_if(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    charge(None).map_err(|e| interrupt(env, e))?;
+    (builtins().r#if)(env, operands)
+}
+
+fn metered_eval(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    charge(None).map_err(|e| interrupt(env, e))?;
+    (builtins().eval)(env, operands)
+}
+
+fn metered_concat(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let size = operands
+        .iter()
+        .map(|operand| match operand {
+            Lval::Str(s) => s.len(),
+            _ => 0,
+        })
+        .sum();
+    charge(Some(size)).map_err(|e| interrupt(env, e))?;
+    (builtins().concat)(env, operands)
+}
+
+fn metered_join(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let size = operands.iter().map(size_of).sum();
+    charge(Some(size)).map_err(|e| interrupt(env, e))?;
+    (builtins().join)(env, operands)
+}
+
+// the bytes a value holds, each element of a list counting for at least one
+fn size_of(value: &Lval) -> usize {
+    match value {
+        Lval::Str(s) | Lval::Sym(s) => s.len(),
+        Lval::Sexpr(values) | Lval::Qexpr(values) => {
+            values.iter().map(|value| size_of(value).max(1)).sum()
+        }
+        _ => 1,
+    }
+}
+
+/// A bebop environment whose looping and allocating builtins draw from the
+/// budget of the sandbox they run in.
+pub fn init_env() -> Lenv {
+    let mut env = bebop_lang::lisp::env::init_env();
+    bebop_lang::lisp::add_builtin(&mut env, "\\", metered_lambda);
+    bebop_lang::lisp::add_builtin(&mut env, "if", metered_if);
+    bebop_lang::lisp::add_builtin(&mut env, "eval", metered_eval);
+    bebop_lang::lisp::add_builtin(&mut env, "concat", metered_concat);
+    bebop_lang::lisp::add_builtin(&mut env, "join", metered_join);
+    env
+}
+
+/// Evaluates a program, refusing ones nested too deeply to parse safely.
+pub fn lisp(env: &mut Lenv, input: &str) -> String {
+    if nesting(input) > MAX_NESTING {
+        record(ExecutionError::Depth);
+        return String::new();
+    }
+
+    bebop_lang::lisp::lisp(env, input)
+}
+
+/// Parses a program into its top level expressions, or the byte offset of
+/// the first expression that could not be parsed.
+pub fn parse(input: &str) -> Result<Vec<Lval>, usize> {
+    if nesting(input) > MAX_NESTING {
+        record(ExecutionError::Depth);
+        return Ok(vec![]);
+    }
+
+    match bebop_lang::lisp::parser::parse(input) {
+        Ok((_, Lval::Sexpr(exprs))) => Ok(exprs),
+        Ok((_, expr)) => Ok(vec![expr]),
+        Err(nom::Err::Error(SyntaxError::Nom(rest, _)))
+        | Err(nom::Err::Failure(SyntaxError::Nom(rest, _))) => Err(input.len() - rest.len()),
+        Err(_) => Err(0),
+    }
+}
+
+/// Evaluates a single expression, describing any error the way bebop would.
+pub fn eval(env: &mut Lenv, expr: Lval) -> Result<Lval, String> {
+    bebop_lang::lisp::eval::eval(env, expr)
+        .map_err(|e| format!("{:?}", e).trim_start_matches("Error: ").to_string())
+}
+
+fn nesting(input: &str) -> usize {
+    let mut in_string = false;
+    let mut depth: usize = 0;
+    let mut max_depth = 0;
+
+    for c in input.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' if !in_string => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            ')' | ']' if !in_string => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    max_depth
+}
+
+#[cfg(test)]
+fn test_sandbox() -> Sandbox {
+    Sandbox {
+        limits: Limits {
+            max_steps: 200,
+            max_output: 1024,
+            stack_size: 16 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+        },
+        permits: Arc::new(Semaphore::new(2)),
+    }
+}
+
+#[cfg(test)]
+async fn run_program(program: String) -> Result<String, ExecutionError> {
+    test_sandbox()
+        .run(move || {
+            let mut env = init_env();
+            lisp(&mut env, &format!("{}\n{}", crate::GLOBAL_PRELUDE, program))
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_sandbox_runs_programs() {
+    let output = run_program(String::from(
+        r#"(h1 "hi") (concat "" (rec 3 "" (\ [n next] [concat "." (next)])))"#,
+    ))
+    .await;
+    assert_eq!(output, Ok(String::from("<h1>hi</h1>...")));
+}
+
+#[tokio::test]
+async fn test_sandbox_stops_runaway_programs() {
+    // recursion that never reaches a base case
+    assert_eq!(
+        run_program(String::from("(fun [loop n] [loop (+ n 1)]) (loop 0)")).await,
+        Err(ExecutionError::Steps(200))
+    );
+    // output that doubles every step
+    assert_eq!(
+        run_program(String::from(
+            r#"(fun [grow s] [grow (concat s s)]) (grow "ab")"#
+        ))
+        .await,
+        Err(ExecutionError::Output(1024))
+    );
+    // lists are as big as what they hold, however few elements
+    let long = "x".repeat(600);
+    assert_eq!(
+        run_program(format!(r#"(join ["{long}"] ["{long}"])"#)).await,
+        Err(ExecutionError::Output(1024))
+    );
+    // nesting too deep to even parse
+    assert_eq!(
+        run_program(format!("{}1{}", "(+ 1 ".repeat(1000), ")".repeat(1000))).await,
+        Err(ExecutionError::Depth)
+    );
+}
@@ -6,6 +6,21 @@ table! {
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
         deleted_at -> Nullable<Timestamp>,
+        last_seen_at -> Nullable<Timestamp>,
+        user_agent -> Nullable<Varchar>,
+        is_remembered -> Bool,
+        token_hash -> Varchar,
+    }
+}
+
+table! {
+    share_link (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        token -> Varchar,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
     }
 }
 
@@ -22,6 +37,15 @@ table! {
     }
 }
 
+table! {
+    username_redirect (id) {
+        id -> Int4,
+        username -> Varchar,
+        user_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     workspace (id) {
         id -> Int4,
@@ -35,6 +59,11 @@ table! {
         content -> Nullable<Text>,
         parent_id -> Int4,
         is_published -> Bool,
+        rendered_html -> Nullable<Text>,
+        rendered_hash -> Nullable<Varchar>,
+        position -> Int4,
+        prelude -> Nullable<Text>,
+        style -> Nullable<Text>,
     }
 }
 
@@ -50,6 +79,31 @@ table! {
     }
 }
 
+table! {
+    workspace_member (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        user_id -> Int4,
+        role -> Varchar,
+        invited_by -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    workspace_revision (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        description -> Varchar,
+        content -> Nullable<Text>,
+        restored_from -> Nullable<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     workspace_type (id) {
         id -> Int4,
@@ -57,12 +111,22 @@ table! {
     }
 }
 
+joinable!(share_link -> workspace (workspace_id));
+joinable!(username_redirect -> user (user_id));
 joinable!(workspace -> workspace_type (type_id));
+joinable!(workspace_member -> user (user_id));
+joinable!(workspace_member -> workspace (workspace_id));
+joinable!(workspace_revision -> user (user_id));
+joinable!(workspace_revision -> workspace (workspace_id));
 
 allow_tables_to_appear_in_same_query!(
     session,
+    share_link,
     user,
+    username_redirect,
     workspace,
     workspace_element,
+    workspace_member,
+    workspace_revision,
     workspace_type,
 );
@@ -1,7 +1,12 @@
+use crate::render_cache::RenderCache;
 use chrono::prelude::*;
 use pwhash::bcrypt;
-use sanitize_html::{rules::predefined::DEFAULT, sanitize_str};
-use std::{fs, io};
+use regex::Regex;
+use sanitize_html::{
+    rules::{pattern::Pattern, predefined::DEFAULT, Element, Rules},
+    sanitize_str,
+};
+use std::{fs, io, sync::OnceLock};
 
 pub fn now() -> chrono::naive::NaiveDateTime {
     Utc::now().naive_local()
@@ -19,6 +24,178 @@ pub fn sanitize_html(input: &str) -> String {
     sanitize_str(&DEFAULT, input).unwrap()
 }
 
+/// Cleans what a program rendered before it goes on a page. Allows what the
+/// global prelude's helpers make and a little more, with `class` on
+/// anything so user stylesheets have something to hold on to. Scripts,
+/// styles and frames go along with whatever is inside them.
+pub fn sanitize_output(input: &str) -> String {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+    let rules = RULES.get_or_init(output_rules);
+    sanitize_str(rules, input).unwrap_or_default()
+}
+
+fn output_rules() -> Rules {
+    let pattern = |re: &str| Pattern::regex(Regex::new(re).unwrap());
+    // a scheme we know, or none at all, so `javascript:` and friends are out
+    let url = || pattern("^(https?:|mailto:)") | !pattern("^[^/?#]*:");
+    let element = |name: &str| Element::new(name).attribute("class", Pattern::any());
+
+    let plain = [
+        "h1", "h2", "h3", "h4", "h5", "h6", "p", "blockquote", "pre", "code", "em", "i", "strong",
+        "b", "s", "strike", "u", "sub", "sup", "small", "mark", "ul", "ol", "li", "hr", "br", "div",
+        "section", "article", "aside", "header", "footer", "figure", "figcaption", "details",
+        "summary", "table", "thead", "tbody", "tfoot", "tr", "caption",
+    ];
+    plain
+        .iter()
+        .fold(Rules::new(), |rules, name| rules.element(element(name)))
+        .element(
+            element("a")
+                .attribute("href", url())
+                .attribute("target", pattern("^_blank$"))
+                .attribute("title", Pattern::any()),
+        )
+        .element(
+            element("img")
+                .attribute("src", url())
+                .attribute("alt", Pattern::any())
+                .attribute("title", Pattern::any())
+                .attribute("width", pattern("^[0-9]+$"))
+                .attribute("height", pattern("^[0-9]+$")),
+        )
+        .element(
+            element("input")
+                .attribute("type", pattern("^checkbox$"))
+                .attribute("checked", Pattern::any())
+                .attribute("disabled", Pattern::any()),
+        )
+        // for `color`, which sets nothing else
+        .element(element("span").attribute(
+            "style",
+            pattern(r"^color: *(#[0-9a-fA-F]{3,8}|[a-zA-Z]+|(rgba?|hsla?)\([0-9., %]*\)) *;?$"),
+        ))
+        .element(element("th").attribute("colspan", pattern("^[0-9]+$")))
+        .element(element("td").attribute("colspan", pattern("^[0-9]+$")))
+        .delete("script")
+        .delete("style")
+        .delete("iframe")
+        .delete("object")
+        .delete("embed")
+        .delete("noscript")
+        .delete("template")
+}
+
+// 256 random bits as hex, for links and the like that must not be guessed
+pub fn random_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// for showing user text as-is, like source code
+pub fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// for user text kept through `sanitize_html`, or written before it was.
+// Sanitizing again leaves its entities be, but quotes still need escaping
+// inside an attribute
+pub fn escape_sanitized(input: &str) -> String {
+    sanitize_html(input).replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+// a strong etag for text we serve, changes whenever the text does
+pub fn etag(content: &str) -> String {
+    format!("\"{}\"", &RenderCache::key(&[content])[..16])
+}
+
+/// Keeps a user's stylesheet to styling. Anything that could make the
+/// browser fetch a url or run script is dropped, along with escapes and
+/// comments that could be used to sneak those past.
+pub fn sanitize_css(input: &str) -> String {
+    let mut css = input.to_string();
+
+    // removing one thing can put together another, so go until nothing changes
+    loop {
+        let before = css.clone();
+
+        css = css.chars().filter(|c| !matches!(c, '\\' | '<' | '\0')).collect();
+        css = strip_between(&css, "/*", "*/", "");
+        css = strip_between(&css, "@import", ";", "");
+        for function in ["url(", "image-set(", "image(", "src(", "element(", "expression("] {
+            css = strip_css_calls(&css, function);
+        }
+        for keyword in ["javascript:", "behavior:", "-moz-binding"] {
+            css = strip_between(&css, keyword, "", "");
+        }
+
+        if css == before {
+            return css;
+        }
+    }
+}
+
+// drops each `start` up to and including the next `end`, or to the end of
+// the text when there isn't one. Matching ignores ascii case.
+fn strip_between(text: &str, start: &str, end: &str, replacement: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(from) = rest.to_ascii_lowercase().find(start) {
+        out.push_str(&rest[..from]);
+        out.push_str(replacement);
+        let after = &rest[from + start.len()..];
+        rest = match after.find(end) {
+            Some(to) if !end.is_empty() => &after[to + end.len()..],
+            Some(_) => after,
+            None => "",
+        };
+    }
+
+    out.push_str(rest);
+    out
+}
+
+// replaces a whole function call, arguments and all, with `none`
+fn strip_css_calls(text: &str, function: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(from) = rest.to_ascii_lowercase().find(function) {
+        out.push_str(&rest[..from]);
+        out.push_str("none");
+
+        let after = &rest[from + function.len()..];
+        let mut depth = 1;
+        let mut quote = None;
+        let mut close = after.len();
+        for (i, c) in after.char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '(') => depth += 1,
+                (None, ')') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = i + 1;
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+        rest = &after[close..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
 // Load public certificate from file.
 pub fn load_certs(filename: &str) -> io::Result<Vec<rustls::Certificate>> {
     // Open certificate file.
@@ -57,3 +234,59 @@ fn test_encryption() {
 
     assert!(verify("password", &h_new));
 }
+
+#[test]
+fn test_sanitize_css() {
+    let kept = "body { color: red; }\n#workspace > h1 { font-size: 2em; }";
+    assert_eq!(sanitize_css(kept), kept);
+
+    let css = sanitize_css(
+        "body { background: URL( \"https://evil.example/?a=)\" ) red; }\n\
+         @import 'https://evil.example/a.css';\n\
+         a { background: u\\72l(https://evil.example); }\n\
+         b { background: u/**/rl(https://evil.example); }\n\
+         i { background: image-set(\"https://evil.example\" 1x); width: expression(alert(1)); }\n\
+         </style><script>alert(1)</script>",
+    );
+    let lower = css.to_ascii_lowercase();
+    assert!(!lower.contains("url("));
+    assert!(!lower.contains("evil.example/a.css"));
+    assert!(!lower.contains("image-set("));
+    assert!(!lower.contains("@import"));
+    assert!(!lower.contains("expression("));
+    assert!(!lower.contains('<'));
+    assert!(css.contains("background: none red;"));
+}
+
+#[test]
+fn test_sanitize_output() {
+    let kept = sanitize_output(
+        "<h1>Title</h1><ul class='tasks'><li><input type='checkbox' checked /> done</li></ul>\
+         <a href='/workspace/1'>in</a><a target='_blank' href='https://example.com'>out</a>\
+         <img src='/cat.png' alt='a cat' /><span style='color: #ff0000;'>◼</span>",
+    );
+    assert!(kept.contains("<h1>Title</h1>"));
+    assert!(kept.contains("<ul class=\"tasks\">"));
+    assert!(kept.contains("type=\"checkbox\""));
+    assert!(kept.contains("checked"));
+    assert!(kept.contains("href=\"/workspace/1\""));
+    assert!(kept.contains("target=\"_blank\""));
+    assert!(kept.contains("<img src=\"/cat.png\" alt=\"a cat\""));
+    assert!(kept.contains("style=\"color: #ff0000;\""));
+
+    let html = sanitize_output(
+        "<script>alert(1)</script><p onclick='alert(1)'>hi</p>\
+         <a href='javascript:alert(1)'>x</a><a href=' JaVaScRiPt :alert(1)'>y</a>\
+         <img src='data:image/svg+xml,<svg onload=alert(1)>' />\
+         <span style='background: url(https://evil.example)'>z</span>\
+         <iframe src='https://evil.example'></iframe><style>p { color: red }</style>",
+    );
+    let lower = html.to_ascii_lowercase();
+    assert!(!lower.contains("<script"));
+    assert!(!lower.contains("alert"));
+    assert!(!lower.contains("javascript"));
+    assert!(!lower.contains("data:"));
+    assert!(!lower.contains("evil.example"));
+    assert!(!lower.contains("<style"));
+    assert!(html.contains("<p>hi</p>"));
+}
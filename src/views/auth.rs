@@ -5,9 +5,20 @@ use super::{
     common::{Footer, Header},
     Body, Document, Head,
 };
+use crate::{models::user::SignupErrors, utils::escape_html};
+
+// what went wrong with one field, under it
+fn field_error(error: &Option<String>) -> String {
+    match error {
+        Some(error) => html! { <small class="error">{error.clone()}</small> },
+        None => String::new(),
+    }
+}
 
 pub struct Signup {
-    error: String,
+    // given back so a rejected signup doesn't start over
+    username: String,
+    errors: SignupErrors,
 }
 
 impl Display for Signup {
@@ -28,23 +39,26 @@ impl Display for Signup {
                             <div>
                                 <label>
                                     <span>"Username"</span>
-                                    <input type="text" name="username" required max=48 pattern="[a-zA-Z0-9]+" />
+                                    <input type="text" name="username" value={escape_html(&self.username)} required maxlength=48 pattern="[a-zA-Z0-9]+" />
                                 </label>
                                 <small>"Use only letters and numbers, please"</small>
+                                {field_error(&self.errors.username)}
                             </div>
                             <div>
                                 <label>
                                     <span>"Password"</span>
-                                    <input type="password" name="password" required max=48 />
+                                    <input type="password" name="password" required minlength=8 maxlength=72 />
                                 </label>
+                                <small>"At least 8 characters, with numbers or symbols as well as letters"</small>
+                                {field_error(&self.errors.password)}
                             </div>
                             <div>
                                 <label>
                                     <span>"Confirm Password"</span>
-                                    <input type="password" name="confirm_password" required max=48 />
+                                    <input type="password" name="confirm_password" required minlength=8 maxlength=72 />
                                 </label>
+                                {field_error(&self.errors.confirm_password)}
                             </div>
-                            <div class="error">{self.error.clone()}</div>
                             <button type="submit">"Signup"</button>
                         </fieldset>
                     </form>
@@ -84,6 +98,12 @@ impl Display for Login {
                                     <input type="password" name="password" required max=48 />
                                 </label>
                             </div>
+                            <div>
+                                <label>
+                                    <input type="checkbox" name="remember" value="true" />
+                                    <span>"Remember me"</span>
+                                </label>
+                            </div>
                             <div class="error">{self.error.clone()}</div>
                             <button type="submit">"Login"</button>
                         </fieldset>
@@ -107,27 +127,30 @@ pub fn login_form(error: Option<String>) -> String {
     let html = Document {
         head: &Head {
             title: "Login".to_string(),
-            description: "Login to Digitheque".to_string()
+            description: "Login to Digitheque".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
     html.to_string()
 }
 
-pub fn signup_form<'a>(error: Option<String>) -> String {
+pub fn signup_form(username: &str, errors: &SignupErrors) -> String {
     let body: Body = Body(vec![
         Box::new(Header {
             expanded_user: None,
         }),
         Box::new(Signup {
-            error: error.unwrap_or(String::from("")),
+            username: username.to_string(),
+            errors: errors.clone(),
         }),
         Box::new(Footer),
     ]);
     let html = Document {
         head: &Head {
             title: "Signup".to_string(),
-            description: "Signup to Digitheque".to_string()
+            description: "Signup to Digitheque".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
@@ -29,6 +29,8 @@ impl Display for Header {
                                         <li><a href="/logout">"Logout"</a></li>
                                         <li><a href={format!("/{}/rss", &user.user.username)}>"Feed"</a></li>
                                         <li><a href="/prelude">"Prelude"</a></li>
+                                        <li><a href="/sessions">"Sessions"</a></li>
+                                        <li><a href="/settings">"Settings"</a></li>
                                     }
                                 }
                             }
@@ -147,7 +149,8 @@ pub fn landing_page(expanded_user: Option<ExpandedUser>) -> String {
     let html = Document {
         head: &Head {
             title: "Digitheque".to_string(),
-            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string()
+            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
@@ -485,7 +488,35 @@ String = Symbol
         l
         (\ [n-1 nthn-1] [tail (nthn-1)]))])
 
-(fun [append n] [eval (cons concat n)])"#</pre>
+(fun [append n] [eval (cons concat n)])
+
+(fun [id-of record] [nth 0 record])
+
+(fun [title-of record] [nth 1 record])
+
+(fun [description-of record] [nth 2 record])
+
+(fun [url-of record] [nth 3 record])
+
+(fun [is-published record] [eval (nth 4 record)])
+
+(fun [updated-at-of record] [nth 5 record])"#</pre>
+                    <h4>"Workspace Values"</h4>
+                    <p>"Each workspace's program knows about the workspace it belongs to: "<code>"title"</code>", "
+                        <code>"description"</code>", "<code>"updated-at"</code>" and "<code>"id"</code>". It also knows the
+                        workspaces around it: "<code>"children"</code>", "<code>"parent"</code>", "<code>"siblings"</code>" and "
+                        <code>"author"</code>" are lists of records, each read with "<code>"id-of"</code>", "<code>"title-of"</code>", "
+                        <code>"description-of"</code>", "<code>"url-of"</code>", "<code>"is-published"</code>" and "
+                        <code>"updated-at-of"</code>". On your public pages, only published workspaces are listed. All of these
+                        come already escaped for html, so they can go straight into your page. To list the children of a
+                        workspace:"</p>
+                    <pre>r#"|(ul (append (cons "" (map children (\ [c] [li (a (url-of c) (title-of c))])))))|"#</pre>
+                    <h4>"Including Workspaces"</h4>
+                    <p>"A workspace can show the output of another one with "<code>"(include 42)"</code>", where "<code>"42"</code>"
+                        is the number at the end of the other workspace's address. You can include any of your own workspaces,
+                        and anyone's published ones. On your public pages, only published workspaces are included."</p>
+                    <p>"A workspace can't include itself, even by way of others, and includes only go "
+                        <code>"3"</code>" deep."</p>
                 </div>
             </main>
             }
@@ -502,7 +533,8 @@ pub fn bebop_page(expanded_user: Option<ExpandedUser>) -> String {
     let html = Document {
         head: &Head {
             title: "Bebop".to_string(),
-            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string()
+            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
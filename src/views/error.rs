@@ -47,7 +47,8 @@ pub fn error_page(
     let html = Document {
         head: &Head {
             title: format!("Digitheque {}", status_code),
-            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string()
+            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
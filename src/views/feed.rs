@@ -4,12 +4,13 @@ use std::fmt::{self, Display};
 use super::{Body, Document, Head};
 use crate::{
     models,
+    program::RenderResult,
     views::common::{Footer, Header},
-    GLOBAL_PRELUDE,
 };
 
 pub struct FeedWorkspacePage {
     pub workspace: models::feed::FeedWorkspace,
+    pub content: RenderResult,
 }
 
 impl Display for FeedWorkspacePage {
@@ -19,8 +20,46 @@ impl Display for FeedWorkspacePage {
             "{}",
             html! {
                 <main id="workspace-container">
+                    <aside>
+                        <div class="hide-on-mobile">
+                            <h3>"Contents"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Contents"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
                     <section id="workspace-feed">
-                        {self.workspace.workspace.execute_content(format!("{}\n{}", GLOBAL_PRELUDE, self.workspace.user.prelude.clone().unwrap_or(String::from(""))))}
+                        {if let Some(link) = &self.workspace.share_link {
+                            html! {
+                                <p class="notice">
+                                    "This workspace was shared with you through a private link and may change before it is published."
+                                    {match link.expires_at {
+                                        Some(expires_at) => format!(" The link works until {}.", expires_at.format("%Y-%m-%d %H:%M")),
+                                        None => String::new(),
+                                    }}
+                                </p>
+                            }
+                        } else if self.workspace.is_public() {
+                            String::new()
+                        } else {
+                            html! {
+                                <p class="notice">
+                                    "Only you can see this draft, shown the way everyone will once it and the workspaces above it are published. "
+                                    <a href={format!("/workspace/{}", self.workspace.workspace.id)}>"Back to editing"</a>
+                                </p>
+                            }
+                        }}
+                        {self.workspace.breadcrumbs()}
+                        {if self.workspace.shows_diagnostics() {
+                            self.content.diagnostics_html()
+                        } else if self.content.html.is_empty() && !self.content.diagnostics.is_empty() {
+                            html! { <p class="notice">"This page could not be shown. Please try again later."</p> }
+                        } else {
+                            String::new()
+                        }}
+                        {self.content.html.clone()}
                     </section>
                 </main>
             }
@@ -30,7 +69,8 @@ impl Display for FeedWorkspacePage {
 
 pub fn workspace_page(
     expanded_user: Option<models::user::ExpandedUser>,
-    workspace: models::feed::FeedWorkspace
+    workspace: models::feed::FeedWorkspace,
+    content: RenderResult,
 ) -> String {
     let header = Header {
         expanded_user: expanded_user.clone(),
@@ -40,13 +80,15 @@ pub fn workspace_page(
     body.0.push(Box::new(header));
     body.0.push(Box::new(FeedWorkspacePage {
         workspace: workspace.clone(),
+        content,
     }));
     body.0.push(Box::new(Footer));
 
     let html = Document {
         head: &Head {
-            title: workspace.workspace.name,
-            description: workspace.workspace.description.clone()
+            title: workspace.workspace.name.clone(),
+            description: workspace.workspace.description.clone(),
+            stylesheets: workspace.stylesheets(),
         },
         body: &body,
     };
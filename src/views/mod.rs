@@ -9,7 +9,7 @@ use std::fmt::{self, Display};
 
 use html_to_string_macro::html;
 
-use crate::DOMAIN;
+use crate::{utils::escape_sanitized, DOMAIN};
 
 pub struct Document<'a> {
     pub head: &'a Head,
@@ -36,6 +36,8 @@ pub struct Head {
     // url: String,
     title: String,
     description: String,
+    // the author's own stylesheets, on their workspace pages, in cascade order
+    stylesheets: Vec<String>,
 }
 
 impl Display for Head {
@@ -47,14 +49,18 @@ impl Display for Head {
                 <head>
                     <meta name="viewport" content="width=device-width, initial-scale=1" />
                     <meta charset="utf-8" />
-                    <title>{self.title.clone()}</title>
-                    <meta property="description" content={self.description.clone()} />
-                    <meta property="og:title" content={self.title.clone()} />
+                    <title>{escape_sanitized(&self.title)}</title>
+                    <meta property="description" content={escape_sanitized(&self.description)} />
+                    <meta property="og:title" content={escape_sanitized(&self.title)} />
                     <meta property="og:type" content="website" />
                     <meta property="og:image" content={format!("{}/digitheque.png", DOMAIN)} />
-                    <meta property="og:description" content={self.description.clone()} />
+                    <meta property="og:description" content={escape_sanitized(&self.description)} />
                     <link rel="stylesheet" href="/styles/fonts.css" />
                     <link rel="stylesheet" href="/styles/style.css" />
+                    {self.stylesheets
+                        .iter()
+                        .map(|href| html! { <link rel="stylesheet" href={href} /> })
+                        .collect::<String>()}
                     <link rel="icon" type="image/x-icon" href="/favicon.ico" />
                     <link rel="manifest" href="manifest.json" />
                 </head>
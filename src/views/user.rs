@@ -3,7 +3,9 @@ use std::fmt::{self, Display};
 
 use super::{Body, Document, Head};
 use crate::{
-    models,
+    models::{self, session::csrf_input},
+    program::RenderResult,
+    utils::escape_html,
     views::common::{Footer, Header},
     views::workspace,
 };
@@ -11,8 +13,9 @@ use crate::{
 pub fn profile_page(
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    content: RenderResult,
 ) -> String {
-    workspace::workspace_page(expanded_user, workspace)
+    workspace::workspace_page(expanded_user, workspace, content)
 }
 
 pub fn style_edit_page(
@@ -34,7 +37,8 @@ pub fn style_edit_page(
     let html = Document {
         head: &Head {
             title: "Digitheque".to_string(),
-            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string()
+            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
@@ -60,13 +64,150 @@ pub fn prelude_edit_page(
     let html = Document {
         head: &Head {
             title: "Digitheque".to_string(),
-            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string()
+            description: "Digitheque: Online Publishing! Draft and publish your custom magazines, pamphlets, and notes.".to_string(),
+            stylesheets: vec![],
         },
         body: &body,
     };
     format!("{}", html)
 }
 
+pub fn sessions_page(
+    expanded_user: models::user::ExpandedUser,
+    sessions: Vec<models::session::Session>,
+) -> String {
+    let header = Header {
+        expanded_user: Some(expanded_user.clone()),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(Sessions {
+        expanded_user,
+        sessions,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: "Sessions".to_string(),
+            description: "Where you are logged in".to_string(),
+            stylesheets: vec![],
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn settings_page(expanded_user: models::user::ExpandedUser, message: Option<String>) -> String {
+    let header = Header {
+        expanded_user: Some(expanded_user.clone()),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(Settings {
+        expanded_user,
+        message,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: "Settings".to_string(),
+            description: "Your account".to_string(),
+            stylesheets: vec![],
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn delete_account_page(expanded_user: models::user::ExpandedUser) -> String {
+    let header = Header {
+        expanded_user: Some(expanded_user.clone()),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(DeleteAccount { expanded_user }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: "Delete account".to_string(),
+            description: "Close your account".to_string(),
+            stylesheets: vec![],
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub struct Sessions {
+    expanded_user: models::user::ExpandedUser,
+    sessions: Vec<models::session::Session>,
+}
+
+impl Sessions {
+    fn session_rows(&self) -> String {
+        self.sessions
+            .iter()
+            .map(|session| {
+                html! {
+                    <tr>
+                        <td>{escape_html(&session.device())}</td>
+                        <td>{session.created_at.format("%Y-%m-%d %H:%M").to_string()}</td>
+                        <td>{session.last_seen().format("%Y-%m-%d %H:%M").to_string()}</td>
+                        <td>
+                            {if session.id == self.expanded_user.session.id {
+                                String::from("This session")
+                            } else {
+                                session.revoke_form(&self.expanded_user.csrf_token())
+                            }}
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<String>()
+    }
+}
+
+impl Display for Sessions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main>
+                    <section id="sessions">
+                        <h2>"Sessions"</h2>
+                        <p>"Everywhere you are logged in right now. Revoking a session logs that device out."</p>
+                        <table class="sessions">
+                            <thead>
+                                <tr>
+                                    <th>"Device"</th>
+                                    <th>"Logged in"</th>
+                                    <th>"Last seen"</th>
+                                    <th></th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {self.session_rows()}
+                            </tbody>
+                        </table>
+                        {if self.sessions.len() > 1 {
+                            models::session::Session::revoke_others_form(&self.expanded_user.csrf_token())
+                        } else {
+                            String::new()
+                        }}
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
 pub struct StyleEdit {
     expanded_user: models::user::ExpandedUser,
     message: Option<String>,
@@ -82,8 +223,9 @@ impl Display for StyleEdit {
                     <section id="edit-style">
                         <h3>"Edit Style"</h3>
                         <p>{self.message.clone().unwrap_or_default()}</p>
-                            <form action="/style" method="POST">
-                                <textarea name="style">{self.expanded_user.user.style.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n"))}</textarea>
+                            <form action="/stylesheet" method="POST">
+                                {csrf_input(&self.expanded_user.csrf_token())}
+                                <textarea name="style">{escape_html(&self.expanded_user.user.style.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n")))}</textarea>
                                 <button type="submit">"Submit"</button>
                             </form>
                     </section>
@@ -111,7 +253,8 @@ impl Display for PreludeEdit {
                         <p>"This code will run each time your workspaces get rendered."</p>
                         <p>{self.message.clone().unwrap_or_default()}</p>
                         <form action="/prelude" method="POST">
-                            <textarea name="prelude">{self.expanded_user.user.prelude.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n"))}</textarea>
+                            {csrf_input(&self.expanded_user.csrf_token())}
+                            <textarea name="prelude">{escape_html(&self.expanded_user.user.prelude.clone().unwrap_or(String::from("# Edit me to get started!\nMake sure to save using the button at the bottom.\n")))}</textarea>
                             <button type="submit">"Submit"</button>
                         </form>
                     </section>
@@ -120,3 +263,86 @@ impl Display for PreludeEdit {
         )
     }
 }
+
+pub struct Settings {
+    expanded_user: models::user::ExpandedUser,
+    message: Option<String>,
+}
+
+impl Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let csrf_token = self.expanded_user.csrf_token();
+        write!(
+            f,
+            "{}",
+            html! {
+                <main>
+                    <section id="settings">
+                        <h2>"Settings"</h2>
+                        <p>{self.message.clone().unwrap_or_default()}</p>
+                        <h3>"Username"</h3>
+                        <p>"Your feed and public workspaces live under your username. Links with an old one keep working."</p>
+                        <form action="/settings/username" method="POST">
+                            {csrf_input(&csrf_token)}
+                            <label>
+                                <span>"Username"</span>
+                                <input type="text" name="username" value={self.expanded_user.user.username.clone()} required />
+                            </label>
+                            <button type="submit">"Change username"</button>
+                        </form>
+                        <h3>"Password"</h3>
+                        <p>"Changing your password logs you out everywhere else."</p>
+                        <form action="/settings/password" method="POST">
+                            {csrf_input(&csrf_token)}
+                            <label>
+                                <span>"Current password"</span>
+                                <input type="password" name="current_password" autocomplete="current-password" required />
+                            </label>
+                            <label>
+                                <span>"New password"</span>
+                                <input type="password" name="new_password" autocomplete="new-password" required />
+                            </label>
+                            <label>
+                                <span>"Confirm new password"</span>
+                                <input type="password" name="confirm_password" autocomplete="new-password" required />
+                            </label>
+                            <button type="submit">"Change password"</button>
+                        </form>
+                        <h3>"Delete account"</h3>
+                        <p><a class="red" href="/settings/delete">"Delete your account"</a></p>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+pub struct DeleteAccount {
+    expanded_user: models::user::ExpandedUser,
+}
+
+impl Display for DeleteAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main>
+                    <section id="settings">
+                        <h2>"Delete account"</h2>
+                        <p>"This logs you out everywhere and takes down your feed and every one of your workspaces. Nobody will be able to read them anymore, and your username will not be free for anyone else."</p>
+                        <form action="/settings/delete" method="POST">
+                            {csrf_input(&self.expanded_user.csrf_token())}
+                            <label>
+                                <span>"Enter your password to confirm"</span>
+                                <input type="password" name="password" autocomplete="current-password" required />
+                            </label>
+                            <button type="submit" class="submit-delete">"✖ Delete my account"</button>
+                        </form>
+                        <p><a href="/settings">"Keep my account"</a></p>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
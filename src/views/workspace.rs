@@ -3,15 +3,17 @@ use std::fmt::{self, Display};
 
 use super::{Body, Document, Head};
 use crate::{
-    models,
+    models::{self, session::csrf_input, workspace_member::Role},
+    program::RenderResult,
     views::common::{Footer, Header},
-    GLOBAL_PRELUDE,
 };
 
 #[derive(Clone)]
 pub struct WorkspacePage {
-    expanded_user: models::user::ExpandedUser,
+    csrf_token: String,
+    _expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    content: RenderResult,
 }
 
 impl Display for WorkspacePage {
@@ -25,18 +27,29 @@ impl Display for WorkspacePage {
                         <h2>"Details"</h2>
                         {self.workspace.workspace.details()}
                         <h2>"Actions"</h2>
-                        {self.workspace.workspace.actions(false)}
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <p>{self.workspace.link_to_public_preview()}</p>
                         <div class="hide-on-mobile">
                             <h3>"Subworkspaces"</h3>
-                            {self.workspace.subworkspaces()}
+                            {self.workspace.subworkspaces(&self.csrf_token)}
                         </div>
                         <details class="show-on-mobile">
                             <summary>"Subworkspaces"</summary>
-                            {self.workspace.subworkspaces()}
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
                         </details>
                     </aside>
                     <section id="workspace">
-                        {self.workspace.workspace.execute_content(format!("{}\n{}", GLOBAL_PRELUDE, self.expanded_user.user.prelude.clone().unwrap_or(String::from(""))))}
+                        {self.workspace.breadcrumbs()}
+                        {self.content.diagnostics_html()}
+                        {self.content.html.clone()}
                     </section>
                 </main>
             }
@@ -46,8 +59,10 @@ impl Display for WorkspacePage {
 
 #[derive(Clone)]
 pub struct WorkspaceEdit {
+    csrf_token: String,
     _expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    content: RenderResult,
 }
 
 impl Display for WorkspaceEdit {
@@ -61,19 +76,484 @@ impl Display for WorkspaceEdit {
                         <h2>"Details"</h2>
                         {self.workspace.workspace.details()}
                         <h2>"Actions"</h2>
-                        {self.workspace.workspace.actions(true)}
+                        {self.workspace.actions(&self.csrf_token, true)}
                         <div class="hide-on-mobile">
                             <h3>"Subworkspaces"</h3>
-                            {self.workspace.subworkspaces()}
+                            {self.workspace.subworkspaces(&self.csrf_token)}
                         </div>
                         <details class="show-on-mobile">
                             <summary>"Subworkspaces"</summary>
-                            {self.workspace.subworkspaces()}
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
                         </details>
                     </aside>
                     <section id="edit-workspace">
+                        {self.workspace.breadcrumbs()}
                         <h2>"Edit Workspace"</h2>
-                        {self.workspace.workspace.edit_self_form()}
+                        <div class="split-pane">
+                            {self.workspace.workspace.edit_self_form(&self.csrf_token, &self.content.diagnostics_html())}
+                            <div id="workspace-preview">
+                                {self.content.html.clone()}
+                            </div>
+                        </div>
+                        <script src="/js/preview.js" defer></script>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceHistory {
+    csrf_token: String,
+    workspace: models::workspace::WorkspaceWithChildren,
+    revisions: Vec<models::workspace_revision::RevisionWithAuthor>,
+}
+
+impl WorkspaceHistory {
+    fn revision_rows(&self) -> String {
+        let workspace_id = self.workspace.workspace.id;
+
+        self.revisions
+            .iter()
+            .enumerate()
+            .map(|(i, revision_with_author)| {
+                let revision = &revision_with_author.revision;
+                html! {
+                    <tr>
+                        <td>
+                            <input type="radio" name="from" form="compare-revisions" value={revision.id} {if i == 1 { "checked" } else { "" }} />
+                        </td>
+                        <td>
+                            <input type="radio" name="to" form="compare-revisions" value={revision.id} {if i == 0 { "checked" } else { "" }} />
+                        </td>
+                        <td>
+                            {format!("#{}", revision.id)}
+                            {match revision.restored_from {
+                                Some(restored_from) => format!(" (restored #{})", restored_from),
+                                None => String::new(),
+                            }}
+                        </td>
+                        <td>{revision_with_author.author.clone()}</td>
+                        <td>{revision.created_at.format("%Y-%m-%d %H:%M").to_string()}</td>
+                        <td>
+                            {if i == 0 {
+                                String::from("Current")
+                            } else if self.workspace.role < Role::Editor {
+                                String::new()
+                            } else {
+                                html! {
+                                    <form action={format!("/workspace/{}/history/{}/restore", workspace_id, revision.id)} method="POST">
+                                        {csrf_input(&self.csrf_token)}
+                                        <button type="submit">"Restore"</button>
+                                    </form>
+                                }
+                            }}
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<String>()
+    }
+}
+
+impl Display for WorkspaceHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main id="workspace-container">
+                    <aside>
+                        <h2>"Details"</h2>
+                        {self.workspace.workspace.details()}
+                        <h2>"Actions"</h2>
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <div class="hide-on-mobile">
+                            <h3>"Subworkspaces"</h3>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Subworkspaces"</summary>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
+                    <section id="workspace-history">
+                        {self.workspace.breadcrumbs()}
+                        <h2>"History"</h2>
+                        <p>"Every save is kept here. Restoring an old revision saves it again as the newest one, nothing is ever thrown away."</p>
+                        <form id="compare-revisions" action={format!("/workspace/{}/history/diff", self.workspace.workspace.id)} method="GET"></form>
+                        <table class="revisions">
+                            <thead>
+                                <tr>
+                                    <th>"From"</th>
+                                    <th>"To"</th>
+                                    <th>"Revision"</th>
+                                    <th>"Author"</th>
+                                    <th>"Saved"</th>
+                                    <th></th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {self.revision_rows()}
+                            </tbody>
+                        </table>
+                        <button type="submit" form="compare-revisions">"Compare revisions"</button>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceMove {
+    csrf_token: String,
+    workspace: models::workspace::WorkspaceWithChildren,
+    workspaces: Vec<models::workspace::Workspace>,
+}
+
+impl Display for WorkspaceMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main id="workspace-container">
+                    <aside>
+                        <h2>"Details"</h2>
+                        {self.workspace.workspace.details()}
+                        <h2>"Actions"</h2>
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <div class="hide-on-mobile">
+                            <h3>"Subworkspaces"</h3>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Subworkspaces"</summary>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
+                    <section id="workspace-move">
+                        {self.workspace.breadcrumbs()}
+                        <h2>"Move Workspace"</h2>
+                        <p>"Pick a new parent. Subworkspaces come along for the ride."</p>
+                        {self.workspace.workspace.move_form(&self.csrf_token, &self.workspaces)}
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceShare {
+    csrf_token: String,
+    workspace: models::workspace::WorkspaceWithChildren,
+    links: Vec<models::share_link::ShareLink>,
+}
+
+impl WorkspaceShare {
+    fn link_rows(&self) -> String {
+        self.links
+            .iter()
+            .map(|link| {
+                html! {
+                    <tr>
+                        <td>
+                            <input type="text" readonly value={format!("{}{}", crate::DOMAIN, link.href())} />
+                        </td>
+                        <td>{link.created_at.format("%Y-%m-%d %H:%M").to_string()}</td>
+                        <td>{link.expiry()}</td>
+                        <td>{link.revoke_form(&self.csrf_token)}</td>
+                    </tr>
+                }
+            })
+            .collect::<String>()
+    }
+}
+
+impl Display for WorkspaceShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main id="workspace-container">
+                    <aside>
+                        <h2>"Details"</h2>
+                        {self.workspace.workspace.details()}
+                        <h2>"Actions"</h2>
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <div class="hide-on-mobile">
+                            <h3>"Subworkspaces"</h3>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Subworkspaces"</summary>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
+                    <section id="workspace-share">
+                        {self.workspace.breadcrumbs()}
+                        <h2>"Share"</h2>
+                        <p>"Anyone with one of these links can read this workspace as it is, published or not, without signing in. They can't change anything. Revoke a link to stop it working."</p>
+                        {if self.links.is_empty() {
+                            html! { <p>"There are no links to this workspace yet."</p> }
+                        } else {
+                            html! {
+                                <table class="share-links">
+                                    <thead>
+                                        <tr>
+                                            <th>"Link"</th>
+                                            <th>"Created"</th>
+                                            <th>"Expires"</th>
+                                            <th></th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {self.link_rows()}
+                                    </tbody>
+                                </table>
+                            }
+                        }}
+                        {models::share_link::ShareLink::new_form(self.workspace.workspace.id, &self.csrf_token)}
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceMembers {
+    csrf_token: String,
+    workspace: models::workspace::WorkspaceWithChildren,
+    members: Vec<models::workspace_member::MemberWithUser>,
+}
+
+impl WorkspaceMembers {
+    fn member_rows(&self) -> String {
+        self.members
+            .iter()
+            .map(|member_with_user| {
+                let member = &member_with_user.member;
+                html! {
+                    <tr>
+                        <td>{member_with_user.username.clone()}</td>
+                        <td>{member.role().to_string()}</td>
+                        <td>{member.created_at.format("%Y-%m-%d %H:%M").to_string()}</td>
+                        <td>{member.remove_form(&self.csrf_token)}</td>
+                    </tr>
+                }
+            })
+            .collect::<String>()
+    }
+}
+
+impl Display for WorkspaceMembers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main id="workspace-container">
+                    <aside>
+                        <h2>"Details"</h2>
+                        {self.workspace.workspace.details()}
+                        <h2>"Actions"</h2>
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <div class="hide-on-mobile">
+                            <h3>"Subworkspaces"</h3>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Subworkspaces"</summary>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
+                    <section id="workspace-members">
+                        {self.workspace.breadcrumbs()}
+                        <h2>"Members"</h2>
+                        <p>"Members can open this workspace and everything under it. Viewers read, editors also edit, publish and add subworkspaces, admins also move, delete, share and invite. Inviting a member again changes their role."</p>
+                        {if self.members.is_empty() {
+                            html! { <p>"Nobody else has been let into this workspace yet."</p> }
+                        } else {
+                            html! {
+                                <table class="members">
+                                    <thead>
+                                        <tr>
+                                            <th>"Member"</th>
+                                            <th>"Role"</th>
+                                            <th>"Since"</th>
+                                            <th></th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {self.member_rows()}
+                                    </tbody>
+                                </table>
+                            }
+                        }}
+                        {models::workspace_member::WorkspaceMember::new_form(self.workspace.workspace.id, &self.csrf_token)}
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceShared {
+    shared: Vec<models::workspace_member::SharedWorkspace>,
+}
+
+impl Display for WorkspaceShared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main>
+                    <section id="workspace-shared">
+                        <h2>"Shared with you"</h2>
+                        <p>"Workspaces other people have let you into, along with everything under them."</p>
+                        <ul class="shared">
+                            {if self.shared.is_empty() {
+                                html! { <li>"Nothing has been shared with you yet"</li> }
+                            } else {
+                                self.shared
+                                    .iter()
+                                    .map(|shared| shared.to_html())
+                                    .collect::<String>()
+                            }}
+                        </ul>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceTrash {
+    csrf_token: String,
+    trashed: Vec<models::workspace::TrashedWorkspace>,
+    retention_days: i64,
+}
+
+impl Display for WorkspaceTrash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main>
+                    <section id="workspace-trash">
+                        <h2>"Trash"</h2>
+                        <p>{format!(
+                            "Deleted workspaces stay here for {} days before they are gone for good. Restoring one puts it back where it was, or under your root if that is gone too.",
+                            self.retention_days
+                        )}</p>
+                        <ul class="trash">
+                            {if self.trashed.is_empty() {
+                                html! { <li>"The trash is empty"</li> }
+                            } else {
+                                self.trashed
+                                    .iter()
+                                    .map(|trashed| trashed.to_html(&self.csrf_token))
+                                    .collect::<String>()
+                            }}
+                        </ul>
+                    </section>
+                </main>
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkspaceRevisionDiff {
+    csrf_token: String,
+    workspace: models::workspace::WorkspaceWithChildren,
+    from: models::workspace_revision::WorkspaceRevision,
+    to: models::workspace_revision::WorkspaceRevision,
+}
+
+impl Display for WorkspaceRevisionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            html! {
+                <main id="workspace-container">
+                    <aside>
+                        <h2>"Details"</h2>
+                        {self.workspace.workspace.details()}
+                        <h2>"Actions"</h2>
+                        {self.workspace.actions(&self.csrf_token, false)}
+                        <div class="hide-on-mobile">
+                            <h3>"Subworkspaces"</h3>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"Subworkspaces"</summary>
+                            {self.workspace.subworkspaces(&self.csrf_token)}
+                        </details>
+                        <div class="hide-on-mobile">
+                            <h3>"All workspaces"</h3>
+                            {self.workspace.tree_html()}
+                        </div>
+                        <details class="show-on-mobile">
+                            <summary>"All workspaces"</summary>
+                            {self.workspace.tree_html()}
+                        </details>
+                    </aside>
+                    <section id="workspace-history">
+                        {self.workspace.breadcrumbs()}
+                        <h2>{format!("Revision #{} → #{}", self.from.id, self.to.id)}</h2>
+                        <p>{self.workspace.workspace.link_to_history()}</p>
+                        {self.from.diff(&self.to)}
                     </section>
                 </main>
             }
@@ -84,7 +564,10 @@ impl Display for WorkspaceEdit {
 pub fn workspace_page(
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    content: RenderResult,
 ) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
     let header = Header {
         expanded_user: Some(expanded_user.clone()),
     };
@@ -92,25 +575,42 @@ pub fn workspace_page(
 
     body.0.push(Box::new(header));
     body.0.push(Box::new(WorkspacePage {
-        expanded_user,
+        csrf_token,
+        _expanded_user: expanded_user,
         workspace: workspace.clone(),
+        content,
     }));
     body.0.push(Box::new(Footer));
 
     let html = Document {
         head: &Head {
             title: workspace.workspace.name,
-            description: workspace.workspace.description
+            description: workspace.workspace.description,
+            stylesheets,
         },
         body: &body,
     };
     format!("{}", html)
 }
 
+/// The fragment the editor swaps in as you type, the rendered draft plus
+/// its diagnostics, which replace the ones next to the textarea.
+pub fn preview(content: &RenderResult) -> String {
+    html! {
+        {content.html.clone()}
+        <div id="workspace-diagnostics">
+            {content.diagnostics_html()}
+        </div>
+    }
+}
+
 pub fn edit_workspace_page(
     expanded_user: models::user::ExpandedUser,
     workspace: models::workspace::WorkspaceWithChildren,
+    content: RenderResult,
 ) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
     let header = Header {
         expanded_user: Some(expanded_user.clone()),
     };
@@ -118,15 +618,229 @@ pub fn edit_workspace_page(
 
     body.0.push(Box::new(header));
     body.0.push(Box::new(WorkspaceEdit {
+        csrf_token,
         _expanded_user: expanded_user,
         workspace: workspace.clone(),
+        content,
     }));
     body.0.push(Box::new(Footer));
 
     let html = Document {
         head: &Head {
             title: workspace.workspace.name,
-            description: workspace.workspace.description
+            description: workspace.workspace.description,
+            stylesheets,
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn history_page(
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    revisions: Vec<models::workspace_revision::RevisionWithAuthor>,
+) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceHistory {
+        csrf_token,
+        workspace: workspace.clone(),
+        revisions,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: format!("History of {}", workspace.workspace.name),
+            description: workspace.workspace.description,
+            stylesheets,
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn share_page(
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    links: Vec<models::share_link::ShareLink>,
+) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceShare {
+        csrf_token,
+        workspace: workspace.clone(),
+        links,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: format!("Share {}", workspace.workspace.name),
+            description: workspace.workspace.description,
+            stylesheets,
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn members_page(
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    members: Vec<models::workspace_member::MemberWithUser>,
+) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceMembers {
+        csrf_token,
+        workspace: workspace.clone(),
+        members,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: format!("Members of {}", workspace.workspace.name),
+            description: workspace.workspace.description,
+            stylesheets,
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn shared_page(
+    expanded_user: models::user::ExpandedUser,
+    shared: Vec<models::workspace_member::SharedWorkspace>,
+) -> String {
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceShared { shared }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: "Shared with you".to_string(),
+            description: "Workspaces shared with you".to_string(),
+            stylesheets: vec![],
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn revision_diff_page(
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    from: models::workspace_revision::WorkspaceRevision,
+    to: models::workspace_revision::WorkspaceRevision,
+) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceRevisionDiff {
+        csrf_token,
+        workspace: workspace.clone(),
+        from,
+        to,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: format!("History of {}", workspace.workspace.name),
+            description: workspace.workspace.description,
+            stylesheets,
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn trash_page(
+    expanded_user: models::user::ExpandedUser,
+    trashed: Vec<models::workspace::TrashedWorkspace>,
+    retention_days: i64,
+) -> String {
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceTrash {
+        csrf_token,
+        trashed,
+        retention_days,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: "Trash".to_string(),
+            description: "Deleted workspaces".to_string(),
+            stylesheets: vec![],
+        },
+        body: &body,
+    };
+    format!("{}", html)
+}
+
+pub fn move_page(
+    expanded_user: models::user::ExpandedUser,
+    workspace: models::workspace::WorkspaceWithChildren,
+    workspaces: Vec<models::workspace::Workspace>,
+) -> String {
+    let stylesheets = workspace.stylesheets();
+    let csrf_token = expanded_user.csrf_token();
+    let header = Header {
+        expanded_user: Some(expanded_user),
+    };
+    let mut body = Body(vec![]);
+
+    body.0.push(Box::new(header));
+    body.0.push(Box::new(WorkspaceMove {
+        csrf_token,
+        workspace: workspace.clone(),
+        workspaces,
+    }));
+    body.0.push(Box::new(Footer));
+
+    let html = Document {
+        head: &Head {
+            title: format!("Move {}", workspace.workspace.name),
+            description: workspace.workspace.description,
+            stylesheets,
         },
         body: &body,
     };
@@ -0,0 +1,38 @@
+// Live preview for the workspace editor. A textarea with `data-preview`
+// posts its form there once typing pauses, then puts the rendered draft in
+// #workspace-preview and the fresh diagnostics next to the textarea.
+(function () {
+    const DELAY = 500;
+
+    document.querySelectorAll("textarea[data-preview]").forEach((textarea) => {
+        const form = textarea.closest("form");
+        const target = document.getElementById("workspace-preview");
+        let timer;
+        let latest = 0;
+
+        textarea.addEventListener("input", () => {
+            clearTimeout(timer);
+            timer = setTimeout(async () => {
+                // only the newest request gets to update the page
+                const request = ++latest;
+                const res = await fetch(textarea.dataset.preview, {
+                    method: "POST",
+                    credentials: "same-origin",
+                    body: new URLSearchParams(new FormData(form)),
+                });
+                if (!res.ok || request !== latest) {
+                    return;
+                }
+
+                const fragment = document.createElement("template");
+                fragment.innerHTML = await res.text();
+                const diagnostics = fragment.content.getElementById("workspace-diagnostics");
+                const current = document.getElementById("workspace-diagnostics");
+                if (diagnostics && current) {
+                    current.replaceWith(diagnostics);
+                }
+                target.replaceChildren(fragment.content);
+            }, DELAY);
+        });
+    });
+})();